@@ -2,6 +2,7 @@ use clap::Clap;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::iter;
+use std::ops::Range;
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Kevin K. <kbknapp@gmail.com>")]
@@ -22,15 +23,24 @@ pub struct Opts {
     pub verbose: i32,
 }
 
+lazy_static! {
+    static ref CONTROL_CHARS_RE: Regex =
+        Regex::new(r"[^A-Za-z0-9ㄱ-ㅎㅏ-ㅣ가-힣~!?.,():;*/=+\-\[\]\s\n<>]").unwrap();
+}
+
 pub fn control_chars(text: &str, replacer: &str) -> String {
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"[^A-Za-z0-9ㄱ-ㅎㅏ-ㅣ가-힣~!?.,():;*/=+\-\[\]\s\n<>]").unwrap();
-    }
-    RE.replace_all(text, replacer).into_owned()
+    CONTROL_CHARS_RE.replace_all(text, replacer).into_owned()
 }
 
 pub fn hangul_to_jamo(text: String) -> String {
+    hangul_to_jamo_iter(text.chars().map(|c| (c, ())))
+        .map(|(c, _)| c)
+        .collect()
+}
+
+fn hangul_to_jamo_iter<T: Copy>(
+    chars: impl Iterator<Item = (char, T)>,
+) -> impl Iterator<Item = (char, T)> {
     const CHO: [char; 19] = [
         'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ',
         'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
@@ -43,9 +53,9 @@ pub fn hangul_to_jamo(text: String) -> String {
         '\0', 'ㄱ', 'ㄲ', 'ㄳ', 'ㄴ', 'ㄵ', 'ㄶ', 'ㄷ', 'ㄹ', 'ㄺ', 'ㄻ', 'ㄼ', 'ㄽ', 'ㄾ', 'ㄿ',
         'ㅀ', 'ㅁ', 'ㅂ', 'ㅄ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
     ];
-    text.chars()
-        .flat_map(|c| {
-            if ('가'..='힣').contains(&c) {
+    chars
+        .flat_map(|(c, origin)| {
+            let jamo = if ('가'..='힣').contains(&c) {
                 let c = c as usize;
                 let cho_index = (c - 44032) / 588;
                 let jung_index = (c - 44032 - cho_index * 588) / 28;
@@ -57,26 +67,33 @@ pub fn hangul_to_jamo(text: String) -> String {
                 iter::once(c)
                     .chain(iter::once('\0'))
                     .chain(iter::once('\0'))
-            }
+            };
+            jamo.map(move |c| (c, origin))
         })
-        .filter(|c| c != &'\0')
-        .collect()
+        .filter(|(c, _)| c != &'\0')
 }
 
 pub fn derepeat(text: &str, n: usize) -> String {
+    derepeat_iter(text.chars().map(|c| (c, ())), n)
+        .map(|(c, _)| c)
+        .collect()
+}
+
+fn derepeat_iter<T>(
+    chars: impl Iterator<Item = (char, T)>,
+    n: usize,
+) -> impl Iterator<Item = (char, T)> {
     let mut last_char: char = '𝕊';
     let mut repeat: usize = 0;
-    text.chars()
-        .filter(|c| {
-            if last_char == *c {
-                repeat += 1;
-            } else {
-                repeat = 0;
-                last_char = *c;
-            }
-            repeat < n
-        })
-        .collect()
+    chars.filter(move |(c, _)| {
+        if last_char == *c {
+            repeat += 1;
+        } else {
+            repeat = 0;
+            last_char = *c;
+        }
+        repeat < n
+    })
 }
 
 /*pub fn space_around_ic(text: &str) -> String {
@@ -102,20 +119,225 @@ pub fn derepeat(text: &str, n: usize) -> String {
 }*/
 
 pub fn whitespace_less(text: &str) -> String {
+    whitespace_less_iter(text.trim().chars().map(|c| (c, ())))
+        .map(|(c, _)| c)
+        .collect()
+}
+
+fn whitespace_less_iter<T>(
+    chars: impl Iterator<Item = (char, T)>,
+) -> impl Iterator<Item = (char, T)> {
     let mut last_char: char = '𝕊';
-    text.trim()
-        .chars()
-        .filter(|c| {
-            if char::is_whitespace(last_char) && char::is_whitespace(*c) {
-                *c == '\t'
-            } else {
-                last_char = *c;
-                true
+    chars.filter(move |(c, _)| {
+        if char::is_whitespace(last_char) && char::is_whitespace(*c) {
+            *c == '\t'
+        } else {
+            last_char = *c;
+            true
+        }
+    })
+}
+
+/// Char and byte ranges of a piece of the source text, both end-exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRange {
+    pub chars: Range<usize>,
+    pub bytes: Range<usize>,
+}
+
+/// Text run through the normalization pipeline that still knows, for every output char,
+/// which char of the source text it came from. Replaced and expanded chars point at the
+/// char they replaced; removed chars simply have no output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedText {
+    text: String,
+    /// Byte offset of every char of `text`.
+    char_starts: Vec<usize>,
+    /// Source char index of every char of `text`.
+    origins: Vec<usize>,
+    /// Byte offset of every source char, followed by the source length.
+    source_bytes: Vec<usize>,
+}
+
+impl NormalizedText {
+    pub fn new(source: &str) -> Self {
+        Self {
+            text: source.to_string(),
+            char_starts: source.char_indices().map(|(i, _)| i).collect(),
+            origins: (0..source.chars().count()).collect(),
+            source_bytes: source
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(iter::once(source.len()))
+                .collect(),
+        }
+    }
+    fn map_chars<I: Iterator<Item = (char, usize)>>(
+        self,
+        f: impl FnOnce(iter::Zip<std::vec::IntoIter<char>, std::vec::IntoIter<usize>>) -> I,
+    ) -> Self {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let (text, origins): (String, _) = f(chars.into_iter().zip(self.origins)).unzip();
+        Self {
+            char_starts: text.char_indices().map(|(i, _)| i).collect(),
+            text,
+            origins,
+            source_bytes: self.source_bytes,
+        }
+    }
+    pub fn control_chars(self, replacer: &str) -> Self {
+        let matches = CONTROL_CHARS_RE
+            .find_iter(&self.text)
+            .map(|m| m.start())
+            .collect::<Vec<_>>();
+        let char_starts = self.char_starts.clone();
+        let replacer = replacer.chars().collect::<Vec<_>>();
+        self.map_chars(|chars| {
+            chars
+                .zip(char_starts)
+                .flat_map(move |((c, origin), start)| {
+                    let replaced = if matches.binary_search(&start).is_ok() {
+                        replacer.clone()
+                    } else {
+                        vec![c]
+                    };
+                    replaced.into_iter().map(move |c| (c, origin))
+                })
+        })
+    }
+    pub fn derepeat(self, n: usize) -> Self {
+        self.map_chars(|chars| derepeat_iter(chars, n))
+    }
+    pub fn whitespace_less(self) -> Self {
+        self.map_chars(|chars| {
+            let mut chars = chars
+                .skip_while(|(c, _)| c.is_whitespace())
+                .collect::<Vec<_>>();
+            while matches!(chars.last(), Some((c, _)) if c.is_whitespace()) {
+                chars.pop();
             }
+            whitespace_less_iter(chars.into_iter())
         })
-        .collect()
+    }
+    pub fn hangul_to_jamo(self) -> Self {
+        self.map_chars(hangul_to_jamo_iter)
+    }
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+    pub fn into_string(self) -> String {
+        self.text
+    }
+    /// Maps the byte range `bytes` of the normalized text back to the source text. An empty
+    /// range maps to an empty range at the source position of the following char.
+    pub fn source_range(&self, bytes: Range<usize>) -> SourceRange {
+        let char_index = |byte: usize| match self.char_starts.binary_search(&byte) {
+            Ok(i) | Err(i) => i,
+        };
+        let (start, end) = (char_index(bytes.start), char_index(bytes.end));
+        let source_start = match self.origins.get(start) {
+            Some(origin) => *origin,
+            None => self.source_bytes.len() - 1,
+        };
+        let source_end = if end > start {
+            self.origins[end - 1] + 1
+        } else {
+            source_start
+        };
+        SourceRange {
+            chars: source_start..source_end,
+            bytes: self.source_bytes[source_start]..self.source_bytes[source_end],
+        }
+    }
+}
+
+/// Which normalization steps to run. Steps run in the order `control_chars`,
+/// `whitespace_less`, `derepeat`, `hangul_to_jamo`; the default is the profile the
+/// tokenizer and the noun trainer have always used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizeConfig {
+    /// Replacement for unsupported chars, `None` keeps them as they are.
+    pub control_chars: Option<String>,
+    pub whitespace_less: bool,
+    /// Longest run of a repeated char to keep, `None` keeps every repeat.
+    pub derepeat: Option<usize>,
+    pub hangul_to_jamo: bool,
 }
 
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        Self {
+            control_chars: Some("_".to_string()),
+            whitespace_less: true,
+            derepeat: Some(3),
+            hangul_to_jamo: false,
+        }
+    }
+}
+
+impl From<&Opts> for NormalizeConfig {
+    fn from(opts: &Opts) -> Self {
+        Self {
+            control_chars: opts.control_chars.clone(),
+            whitespace_less: opts.whitespace_less,
+            derepeat: opts.repeat,
+            hangul_to_jamo: opts.hangul_to_jamo,
+        }
+    }
+}
+
+impl NormalizeConfig {
+    /// A profile that leaves the text untouched.
+    pub fn none() -> Self {
+        Self {
+            control_chars: None,
+            whitespace_less: false,
+            derepeat: None,
+            hangul_to_jamo: false,
+        }
+    }
+    pub fn apply(&self, text: &str) -> String {
+        let text = match &self.control_chars {
+            Some(c) => control_chars(text, c),
+            None => text.to_string(),
+        };
+        let text = match self.whitespace_less {
+            true => whitespace_less(&text),
+            false => text,
+        };
+        let text = match self.derepeat {
+            Some(n) => derepeat(&text, n),
+            None => text,
+        };
+        match self.hangul_to_jamo {
+            true => hangul_to_jamo(text),
+            false => text,
+        }
+    }
+    /// Same as `apply`, but keeps track of where every output char came from.
+    pub fn apply_aligned(&self, text: &str) -> NormalizedText {
+        let text = NormalizedText::new(text);
+        let text = match &self.control_chars {
+            Some(c) => text.control_chars(c),
+            None => text,
+        };
+        let text = match self.whitespace_less {
+            true => text.whitespace_less(),
+            false => text,
+        };
+        let text = match self.derepeat {
+            Some(n) => text.derepeat(n),
+            None => text,
+        };
+        match self.hangul_to_jamo {
+            true => text.hangul_to_jamo(),
+            false => text,
+        }
+    }
+}
+
+/// Runs the steps `opts` selects in the CLI's order, `control_chars`, `derepeat`,
+/// `whitespace_less`, `hangul_to_jamo`, which differs from [`NormalizeConfig`]'s.
 pub fn normalize(text: String, opts: &'_ Opts) -> String {
     let text = match &opts.control_chars {
         Some(c) => control_chars(&text, c),
@@ -167,6 +389,62 @@ mod tests {
             "아아아 음음음 호호호 홀홀 ".to_string()
         );
     }
+    #[test]
+    fn it_normalized_text() {
+        let source = "  ㅋㅋㅋㅋㅋ#  세종시 ";
+        let normalized = NormalizedText::new(source)
+            .control_chars("__")
+            .whitespace_less()
+            .derepeat(3);
+        assert_eq!(
+            normalized.as_str(),
+            derepeat(&whitespace_less(&control_chars(source, "__")), 3)
+        );
+        assert_eq!(normalized.as_str(), "ㅋㅋㅋ__ 세종시");
+        let range = normalized.source_range(9..11);
+        assert_eq!(range.chars, 7..8);
+        assert_eq!(&source[range.bytes], "#");
+        let range = normalized.source_range(12..21);
+        assert_eq!(range.chars, 10..13);
+        assert_eq!(&source[range.bytes], "세종시");
+        let jamo = NormalizedText::new("뷁 a").hangul_to_jamo();
+        assert_eq!(jamo.as_str(), "ㅂㅞㄺ a");
+        assert_eq!(jamo.source_range(3..9).chars, 0..1);
+        assert_eq!(jamo.source_range(10..11).chars, 2..3);
+    }
+    #[test]
+    fn it_normalize_config() {
+        let text = "  ㅋㅋㅋㅋㅋ#   뷁뷁뷁뷁 ";
+        let config = NormalizeConfig::default();
+        assert_eq!(
+            config.apply(text),
+            derepeat(&whitespace_less(&control_chars(text, "_")), 3)
+        );
+        assert_eq!(config.apply_aligned(text).as_str(), config.apply(text));
+        let config = NormalizeConfig {
+            derepeat: None,
+            hangul_to_jamo: true,
+            ..NormalizeConfig::default()
+        };
+        assert_eq!(config.apply(text), "ㅋㅋㅋㅋㅋ_ ㅂㅞㄺㅂㅞㄺㅂㅞㄺㅂㅞㄺ");
+        assert_eq!(config.apply_aligned(text).as_str(), config.apply(text));
+        assert_eq!(NormalizeConfig::none().apply(text), text);
+    }
+    #[test]
+    fn it_normalize() {
+        let opts = Opts {
+            input_file_path: None,
+            output_file_path: None,
+            hangul_to_jamo: false,
+            control_chars: None,
+            repeat: Some(2),
+            whitespace_less: true,
+            verbose: 0,
+        };
+        let text = "가\t \t \t나";
+        assert_eq!(normalize(text.to_string(), &opts), "가\t\t\t나");
+        assert_eq!(NormalizeConfig::from(&opts).apply(text), "가\t\t나");
+    }
     /*#[test]
     fn it_space_around_ic() {
         assert_eq!(space_around_ic("ㅋㅋㅋㅠㅠㅜㅠㅜㅎㅎ그른가"), "ㅋㅋㅋㅠㅠㅜㅠㅜㅎㅎ 그른가".to_string());
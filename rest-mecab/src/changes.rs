@@ -0,0 +1,88 @@
+//! Additions to and removals from a tenant's learned nouns, numbered in the order they
+//! happened. The userdic server logs them as it learns and blocks words, so the tokenizer can
+//! pull only what changed since its last sync instead of every noun.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Sequence of the last change a `/nouns` response reflects.
+pub const SEQ_HEADER: &str = "x-nouns-seq";
+/// Changes served per request when the client does not ask for fewer.
+pub const PAGE_SIZE: usize = 10000;
+/// Tokenizer route the userdic server posts a [`Notice`] to, under a subscriber's URL.
+pub const NOTIFY_ROUTE: &str = "/userdic/notify";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub seq: u64,
+    pub op: Op,
+    pub noun: String,
+}
+
+/// Changes after a sequence, oldest first. `last` is the newest sequence logged so far; `more`
+/// is set when changes up to it were left out of this page. The log drops its oldest changes,
+/// `first` is the oldest one it still has, or `last + 1` when it has none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page {
+    #[serde(default)]
+    pub first: u64,
+    pub last: u64,
+    pub changes: Vec<Change>,
+    pub more: bool,
+}
+
+/// Sent to subscribed tokenizers when a tenant's nouns changed: its log has reached `seq`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notice {
+    pub seq: u64,
+}
+
+/// Replays changes onto a list of nouns. A noun added twice is kept once, and replaying a
+/// change that was already applied leaves the list as it is.
+pub fn apply(nouns: Vec<String>, changes: &[Change]) -> Vec<String> {
+    let last_op = changes
+        .iter()
+        .map(|change| (change.noun.as_str(), change.op))
+        .collect::<HashMap<_, _>>();
+    let mut seen = HashSet::new();
+    let mut nouns = nouns;
+    nouns.retain(|noun| {
+        last_op.get(noun.as_str()) != Some(&Op::Remove) && seen.insert(noun.clone())
+    });
+    for change in changes {
+        if last_op[change.noun.as_str()] == Op::Add && seen.insert(change.noun.clone()) {
+            nouns.push(change.noun.clone());
+        }
+    }
+    nouns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_is_idempotent() {
+        let change = |seq, op, noun: &str| Change {
+            seq,
+            op,
+            noun: noun.to_string(),
+        };
+        let changes = vec![
+            change(3, Op::Add, "톩톩"),
+            change(4, Op::Remove, "감스트"),
+            change(5, Op::Add, "감스트"),
+            change(6, Op::Remove, "쒧"),
+        ];
+        let nouns = vec!["쒧".to_string(), "뤣쉙퀡".to_string()];
+        let applied = apply(nouns, &changes);
+        assert_eq!(applied, vec!["뤣쉙퀡", "톩톩", "감스트"]);
+        assert_eq!(apply(applied.clone(), &changes), applied);
+    }
+}
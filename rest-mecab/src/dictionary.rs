@@ -0,0 +1,681 @@
+//! Compiles user dictionary CSVs into the binary format MeCab loads with `-u`, the job
+//! `mecab-dict-index -u` does, so that regenerating the user dictionary needs no build
+//! toolchain and leaves the installed system dictionary alone.
+use anyhow::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The compiled user dictionary, in each build directory.
+pub const USER_DIC: &str = "user.dic";
+
+const DICTIONARY_MAGIC_ID: u32 = 0xef71_8f77;
+const DIC_VERSION: u32 = 102;
+const MECAB_USR_DIC: u32 = 1;
+/// Used when no system dictionary entry shares the context ids of a row without a cost.
+const DEFAULT_WORD_COST: i16 = 3000;
+
+fn read_def<P: AsRef<Path>>(path: P) -> Result<String> {
+    std::fs::read_to_string(path.as_ref())
+        .map_err(|err| Error::msg(format!("{}: {}", path.as_ref().display(), err)))
+}
+
+/// `(A|B)` matches either alternative, a leading `*` matches anything.
+fn match_rewrite_pattern(pattern: &str, field: &str) -> bool {
+    if pattern.starts_with('*') || pattern == field {
+        return true;
+    }
+    if pattern.len() >= 3 && pattern.starts_with('(') && pattern.ends_with(')') {
+        return pattern[1..pattern.len() - 1]
+            .split('|')
+            .any(|alt| alt == field);
+    }
+    false
+}
+
+struct RewritePattern {
+    source: Vec<String>,
+    destination: Vec<String>,
+}
+
+impl RewritePattern {
+    fn rewrite(&self, fields: &[&str]) -> Result<Option<String>> {
+        if self.source.len() > fields.len()
+            || !self
+                .source
+                .iter()
+                .zip(fields)
+                .all(|(pattern, field)| match_rewrite_pattern(pattern, field))
+        {
+            return Ok(None);
+        }
+        let mut output = Vec::with_capacity(self.destination.len());
+        for element in &self.destination {
+            let mut rewritten = String::new();
+            let mut chars = element.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c != '$' {
+                    rewritten.push(c);
+                    continue;
+                }
+                let mut n = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    n = n * 10 + digit as usize;
+                    chars.next();
+                }
+                if n == 0 || n > fields.len() {
+                    return Err(Error::msg(format!("out of range: [{}] {}", element, n)));
+                }
+                rewritten.push_str(fields[n - 1]);
+            }
+            output.push(rewritten);
+        }
+        Ok(Some(output.join(",")))
+    }
+}
+
+/// The `[left rewrite]` and `[right rewrite]` rules of `rewrite.def`, which map a feature to
+/// the keys of `left-id.def` and `right-id.def`.
+pub struct Rewriter {
+    left: Vec<RewritePattern>,
+    right: Vec<RewritePattern>,
+}
+
+impl Rewriter {
+    pub fn parse(def: &str) -> Result<Self> {
+        let mut rewriter = Self {
+            left: vec![],
+            right: vec![],
+        };
+        let mut section = None;
+        for line in def.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = Some(line.to_string());
+                continue;
+            }
+            let mut columns = line.split_whitespace();
+            let (source, destination) = match (columns.next(), columns.next(), columns.next()) {
+                (Some(source), Some(destination), None) => (source, destination),
+                _ => return Err(Error::msg(format!("rewrite.def format error: {}", line))),
+            };
+            let pattern = RewritePattern {
+                source: source.split(',').map(|s| s.to_string()).collect(),
+                destination: destination.split(',').map(|s| s.to_string()).collect(),
+            };
+            match section.as_deref() {
+                Some("[left rewrite]") => rewriter.left.push(pattern),
+                Some("[right rewrite]") => rewriter.right.push(pattern),
+                Some(_) => {}
+                None => return Err(Error::msg(format!("no section for rule: {}", line))),
+            }
+        }
+        Ok(rewriter)
+    }
+    fn apply(rules: &[RewritePattern], feature: &str) -> Result<String> {
+        let fields = feature.split(',').collect::<Vec<_>>();
+        for rule in rules {
+            if let Some(rewritten) = rule.rewrite(&fields)? {
+                return Ok(rewritten);
+            }
+        }
+        Err(Error::msg(format!("no rewrite rule for: {}", feature)))
+    }
+    /// The left and right context features of a feature.
+    pub fn rewrite(&self, feature: &str) -> Result<(String, String)> {
+        Ok((
+            Self::apply(&self.left, feature)?,
+            Self::apply(&self.right, feature)?,
+        ))
+    }
+}
+
+fn parse_context_ids(def: &str) -> Result<HashMap<String, u16>> {
+    def.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut columns = line.trim().splitn(2, &[' ', '\t'][..]);
+            match (columns.next().map(str::parse), columns.next()) {
+                (Some(Ok(id)), Some(feature)) => Ok((feature.trim().to_string(), id)),
+                _ => Err(Error::msg(format!("context id format error: {}", line))),
+            }
+        })
+        .collect()
+}
+
+/// Context ids of the system dictionary a user dictionary is compiled against.
+pub struct ContextIds {
+    rewriter: Rewriter,
+    left: HashMap<String, u16>,
+    right: HashMap<String, u16>,
+    left_size: u32,
+    right_size: u32,
+}
+
+impl ContextIds {
+    pub fn open<P: AsRef<Path>>(mecab_dic_path: P) -> Result<Self> {
+        let path = mecab_dic_path.as_ref();
+        let matrix = read_def(path.join("matrix.def"))?;
+        let sizes = matrix
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<u32>, _>>()?;
+        if sizes.len() != 2 {
+            return Err(Error::msg(
+                "matrix.def should start with its left and right sizes",
+            ));
+        }
+        Ok(Self {
+            rewriter: Rewriter::parse(&read_def(path.join("rewrite.def"))?)?,
+            left: parse_context_ids(&read_def(path.join("left-id.def"))?)?,
+            right: parse_context_ids(&read_def(path.join("right-id.def"))?)?,
+            left_size: sizes[0],
+            right_size: sizes[1],
+        })
+    }
+    /// Left and right context ids of a feature.
+    pub fn ids(&self, feature: &str) -> Result<(u16, u16)> {
+        let (left, right) = self.rewriter.rewrite(feature)?;
+        let lid = self
+            .left
+            .get(&left)
+            .ok_or_else(|| Error::msg(format!("cannot find LEFT-ID for {}", left)))?;
+        let rid = self
+            .right
+            .get(&right)
+            .ok_or_else(|| Error::msg(format!("cannot find RIGHT-ID for {}", right)))?;
+        Ok((*lid, *rid))
+    }
+}
+
+/// A row of a MeCab dictionary CSV. Context ids and cost are optional in user dictionaries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub surface: String,
+    pub left_id: Option<u16>,
+    pub right_id: Option<u16>,
+    pub cost: Option<i16>,
+    pub feature: String,
+}
+
+impl Row {
+    pub fn parse(line: &str) -> Result<Self> {
+        let columns = line.splitn(5, ',').collect::<Vec<_>>();
+        if columns.len() != 5 || columns[0].is_empty() || columns[4].is_empty() {
+            return Err(Error::msg(format!("format error: {}", line)));
+        }
+        let number = |column: &str| -> Result<Option<i64>> {
+            match column.trim() {
+                "" => Ok(None),
+                n => {
+                    Ok(Some(n.parse().map_err(|_| {
+                        Error::msg(format!("format error: {}", line))
+                    })?))
+                }
+            }
+        };
+        // Negative ids ask for them to be assigned, as mecab-dict-index does.
+        let id = |n: Option<i64>| n.filter(|n| *n >= 0).map(|n| n.min(u16::MAX as i64) as u16);
+        Ok(Self {
+            surface: columns[0].to_string(),
+            left_id: id(number(columns[1])?),
+            right_id: id(number(columns[2])?),
+            cost: number(columns[3])?
+                .map(|cost| cost.max(i16::MIN as i64).min(i16::MAX as i64) as i16),
+            feature: columns[4].to_string(),
+        })
+    }
+}
+
+/// A double-array trie laid out the way MeCab's darts reads it: the children of a node whose
+/// base is `b` sit at `b + byte + 1` with `check == b`, and a key ends at `b` itself, whose
+/// base holds `-value - 1`.
+struct DoubleArrayBuilder {
+    base: Vec<i32>,
+    check: Vec<u32>,
+    used: Vec<bool>,
+    next_check_pos: usize,
+    size: usize,
+}
+
+struct DaNode {
+    code: usize,
+    depth: usize,
+    left: usize,
+    right: usize,
+}
+
+impl DoubleArrayBuilder {
+    fn resize(&mut self, size: usize) {
+        if self.base.len() < size {
+            self.base.resize(size, 0);
+            self.check.resize(size, 0);
+            self.used.resize(size, false);
+        }
+    }
+    fn fetch(keys: &[&[u8]], parent: &DaNode) -> Vec<DaNode> {
+        let mut siblings: Vec<DaNode> = Vec::new();
+        for (i, key) in keys.iter().enumerate().take(parent.right).skip(parent.left) {
+            if key.len() < parent.depth {
+                continue;
+            }
+            let code = if key.len() == parent.depth {
+                0
+            } else {
+                key[parent.depth] as usize + 1
+            };
+            if siblings.last().map(|last| last.code) != Some(code) {
+                if let Some(last) = siblings.last_mut() {
+                    last.right = i;
+                }
+                siblings.push(DaNode {
+                    code,
+                    depth: parent.depth + 1,
+                    left: i,
+                    right: parent.right,
+                });
+            }
+        }
+        siblings
+    }
+    fn insert(&mut self, keys: &[&[u8]], values: &[i32], siblings: &[DaNode]) -> usize {
+        let first_code = siblings[0].code;
+        let last_code = siblings[siblings.len() - 1].code;
+        let mut pos = (first_code + 1).max(self.next_check_pos) - 1;
+        let mut nonzero = 0;
+        let mut first = true;
+        let begin = loop {
+            pos += 1;
+            self.resize(pos + 1);
+            if self.check[pos] != 0 {
+                nonzero += 1;
+                continue;
+            } else if first {
+                self.next_check_pos = pos;
+                first = false;
+            }
+            let begin = pos - first_code;
+            self.resize(begin + last_code + 1);
+            if self.used[begin] {
+                continue;
+            }
+            if siblings[1..]
+                .iter()
+                .all(|sibling| self.check[begin + sibling.code] == 0)
+            {
+                break begin;
+            }
+        };
+        if nonzero as f64 / (pos - self.next_check_pos + 1) as f64 >= 0.95 {
+            self.next_check_pos = pos;
+        }
+        self.used[begin] = true;
+        self.size = self.size.max(begin + last_code + 1);
+        for sibling in siblings {
+            self.check[begin + sibling.code] = begin as u32;
+        }
+        for sibling in siblings {
+            let children = Self::fetch(keys, sibling);
+            self.base[begin + sibling.code] = if children.is_empty() {
+                -values[sibling.left] - 1
+            } else {
+                self.insert(keys, values, &children) as i32
+            };
+        }
+        begin
+    }
+    /// Keys must be sorted and unique.
+    fn build(keys: &[&[u8]], values: &[i32]) -> Vec<(i32, u32)> {
+        let mut builder = Self {
+            base: vec![],
+            check: vec![],
+            used: vec![],
+            next_check_pos: 0,
+            size: 1,
+        };
+        builder.resize(8192);
+        builder.base[0] = 1;
+        let root = DaNode {
+            code: 0,
+            depth: 0,
+            left: 0,
+            right: keys.len(),
+        };
+        let siblings = Self::fetch(keys, &root);
+        if !siblings.is_empty() {
+            builder.insert(keys, values, &siblings);
+        }
+        (0..builder.size)
+            .map(|i| (builder.base[i], builder.check[i]))
+            .collect()
+    }
+}
+
+/// Serializes rows into a MeCab user dictionary. Rows must already have context ids and costs.
+fn serialize(rows: &[Row], context_ids: &ContextIds) -> Result<Vec<u8>> {
+    let mut rows = rows.iter().collect::<Vec<_>>();
+    rows.sort_by(|a, b| a.surface.as_bytes().cmp(b.surface.as_bytes()));
+
+    let mut keys: Vec<&[u8]> = Vec::new();
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < rows.len() {
+        let count = rows[i..]
+            .iter()
+            .take_while(|row| row.surface == rows[i].surface)
+            .count();
+        if count > 0xff {
+            return Err(Error::msg(format!(
+                "too many entries for one surface: {}",
+                rows[i].surface
+            )));
+        }
+        keys.push(rows[i].surface.as_bytes());
+        values.push(((i << 8) | count) as i32);
+        i += count;
+    }
+    let units = DoubleArrayBuilder::build(&keys, &values);
+
+    let mut tokens = Vec::with_capacity(rows.len() * 16);
+    let mut features = Vec::new();
+    for row in &rows {
+        let (lid, rid, cost) = match (row.left_id, row.right_id, row.cost) {
+            (Some(lid), Some(rid), Some(cost)) => (lid, rid, cost),
+            _ => return Err(Error::msg(format!("unresolved row: {}", row.surface))),
+        };
+        if lid as u32 >= context_ids.left_size || rid as u32 >= context_ids.right_size {
+            return Err(Error::msg(format!(
+                "context id out of range: {}",
+                row.surface
+            )));
+        }
+        tokens.extend_from_slice(&lid.to_le_bytes());
+        tokens.extend_from_slice(&rid.to_le_bytes());
+        tokens.extend_from_slice(&0u16.to_le_bytes()); // posid
+        tokens.extend_from_slice(&cost.to_le_bytes());
+        tokens.extend_from_slice(&(features.len() as u32).to_le_bytes());
+        tokens.extend_from_slice(&0u32.to_le_bytes()); // compound
+        features.extend_from_slice(row.feature.as_bytes());
+        features.push(0);
+    }
+
+    let dsize = units.len() * 8;
+    let size = 4 * 10 + 32 + dsize + tokens.len() + features.len();
+    let mut out = Vec::with_capacity(size);
+    for n in [
+        DICTIONARY_MAGIC_ID ^ size as u32,
+        DIC_VERSION,
+        MECAB_USR_DIC,
+        rows.len() as u32,
+        context_ids.left_size,
+        context_ids.right_size,
+        dsize as u32,
+        tokens.len() as u32,
+        features.len() as u32,
+        0,
+    ]
+    .iter()
+    {
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+    let mut charset = [0u8; 32];
+    charset[..5].copy_from_slice(b"utf-8");
+    out.extend_from_slice(&charset);
+    for (base, check) in units {
+        out.extend_from_slice(&base.to_le_bytes());
+        out.extend_from_slice(&check.to_le_bytes());
+    }
+    out.extend_from_slice(&tokens);
+    out.extend_from_slice(&features);
+    Ok(out)
+}
+
+/// Median word cost of the system dictionary entries for each context id pair in `wanted`.
+fn system_costs(
+    mecab_dic_path: &Path,
+    wanted: &HashSet<(u16, u16)>,
+) -> Result<HashMap<(u16, u16), i16>> {
+    let mut costs: HashMap<(u16, u16), Vec<i16>> = HashMap::new();
+    for entry in std::fs::read_dir(mecab_dic_path)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // `user-*.csv` are costed user dictionaries left behind by mecab-dict-index -a.
+        if !name.ends_with(".csv") || name.starts_with("user-") {
+            continue;
+        }
+        for line in String::from_utf8_lossy(&std::fs::read(&path)?).lines() {
+            if let Ok(Row {
+                left_id: Some(lid),
+                right_id: Some(rid),
+                cost: Some(cost),
+                ..
+            }) = Row::parse(line)
+            {
+                if wanted.contains(&(lid, rid)) {
+                    costs.entry((lid, rid)).or_default().push(cost);
+                }
+            }
+        }
+    }
+    Ok(costs
+        .into_iter()
+        .map(|(ids, mut costs)| {
+            costs.sort_unstable();
+            (ids, costs[costs.len() / 2])
+        })
+        .collect())
+}
+
+/// Reads every CSV in `csv_dir`, fills in missing context ids from the rewrite rules of the
+/// system dictionary under `mecab_dic_path` and missing costs from system entries with the
+/// same context ids, and writes the compiled dictionary to `out_path`. Returns the number of
+/// entries; with none, nothing is written.
+pub fn compile_user_dic<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    mecab_dic_path: P,
+    csv_dir: Q,
+    out_path: R,
+) -> Result<usize> {
+    let path = mecab_dic_path.as_ref();
+    let mut csv_paths = std::fs::read_dir(csv_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    csv_paths.retain(|p| p.extension().and_then(|ext| ext.to_str()) == Some("csv"));
+    csv_paths.sort();
+
+    let mut rows = Vec::new();
+    for csv_path in csv_paths {
+        for line in std::fs::read_to_string(&csv_path)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            rows.push(
+                Row::parse(line)
+                    .map_err(|err| Error::msg(format!("{}: {}", csv_path.display(), err)))?,
+            );
+        }
+    }
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let context_ids = ContextIds::open(path)?;
+    for row in rows.iter_mut() {
+        if row.left_id.is_none() || row.right_id.is_none() {
+            let (lid, rid) = context_ids.ids(&row.feature)?;
+            row.left_id = row.left_id.or(Some(lid));
+            row.right_id = row.right_id.or(Some(rid));
+        }
+    }
+    let wanted = rows
+        .iter()
+        .filter(|row| row.cost.is_none())
+        .filter_map(|row| Some((row.left_id?, row.right_id?)))
+        .collect::<HashSet<_>>();
+    if !wanted.is_empty() {
+        let costs = system_costs(path, &wanted)?;
+        for row in rows.iter_mut().filter(|row| row.cost.is_none()) {
+            let ids = (row.left_id.unwrap_or(0), row.right_id.unwrap_or(0));
+            row.cost = Some(costs.get(&ids).copied().unwrap_or(DEFAULT_WORD_COST));
+        }
+    }
+
+    std::fs::write(out_path, serialize(&rows, &context_ids)?)?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    /// The lookup MeCab's darts does on the array.
+    fn exact_match(units: &[(i32, u32)], key: &[u8]) -> Option<i32> {
+        let mut b = units[0].0;
+        for byte in key {
+            let p = (b + *byte as i32 + 1) as usize;
+            match units.get(p) {
+                Some((base, check)) if *check == b as u32 => b = *base,
+                _ => return None,
+            }
+        }
+        match units.get(b as usize) {
+            Some((base, check)) if *check == b as u32 && *base < 0 => Some(-base - 1),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn double_array_lookup() {
+        let mut keys = vec![
+            "가",
+            "가나",
+            "가나다",
+            "나",
+            "a",
+            "ab",
+            "b",
+            "감스트",
+            "뤣쉙퀡",
+        ];
+        keys.sort();
+        let keys = keys.iter().map(|k| k.as_bytes()).collect::<Vec<_>>();
+        let values = (0..keys.len() as i32).map(|i| i * 7).collect::<Vec<_>>();
+        let units = DoubleArrayBuilder::build(&keys, &values);
+        for (key, value) in keys.iter().zip(&values) {
+            assert_eq!(exact_match(&units, key), Some(*value));
+        }
+        assert_eq!(exact_match(&units, "가나다라".as_bytes()), None);
+        assert_eq!(exact_match(&units, "감".as_bytes()), None);
+        assert_eq!(exact_match(&units, b"c"), None);
+    }
+
+    #[test]
+    fn rewrite_and_context_ids() {
+        let rewriter = Rewriter::parse(
+            "[unigram rewrite]\n*,*,*,*,*,*,*,* $1,$2,$3,$4,$5,$6,$7,$8\n\n\
+             [left rewrite]\n(JKS|JKO),*,*,*,*,*,*,* $1,*,*,$4,*,*,*,*\n\
+             *,*,*,*,*,*,*,* $1,$2,$3,*,$5,*,*,*\n\n\
+             [right rewrite]\n*,*,*,*,*,*,*,* $1,$2,$3,*,*,*,*,*\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rewriter.rewrite("NNP,*,T,감스트,*,*,*,*").unwrap(),
+            (
+                "NNP,*,T,*,*,*,*,*".to_string(),
+                "NNP,*,T,*,*,*,*,*".to_string()
+            )
+        );
+        assert_eq!(
+            rewriter.rewrite("JKS,*,F,가,*,*,*,*").unwrap().0,
+            "JKS,*,*,가,*,*,*,*"
+        );
+        assert!(rewriter.rewrite("NNP").is_err());
+    }
+
+    #[test]
+    fn compile_user_dic_layout() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-dic-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("user-dic")).unwrap();
+        std::fs::write(dir.join("matrix.def"), "3 3\n0 0 0\n").unwrap();
+        std::fs::write(
+            dir.join("rewrite.def"),
+            "[left rewrite]\n*,*,* $1,*,$3\n[right rewrite]\n*,*,* $1,*,$3\n",
+        )
+        .unwrap();
+        let ids = "0 BOS/EOS,*,*\n1 NNP,*,T\n2 NNP,*,F\n";
+        std::fs::write(dir.join("left-id.def"), ids).unwrap();
+        std::fs::write(dir.join("right-id.def"), ids).unwrap();
+        std::fs::write(
+            dir.join("NNP.csv"),
+            "서울,2,2,100,NNP,*,F\n부산,2,2,300,NNP,*,F\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("user-dic/brand.csv"),
+            "감스트,,,,NNP,*,F,감스트\n뤣쉙퀡,,,-500,NNP,*,T,뤣쉙퀡\n감스트,1,1,10,NNP,*,T",
+        )
+        .unwrap();
+
+        let out_path = dir.join(USER_DIC);
+        assert_eq!(
+            compile_user_dic(&dir, dir.join("user-dic"), &out_path).unwrap(),
+            3
+        );
+        let bytes = std::fs::read(&out_path).unwrap();
+        assert_eq!(
+            read_u32(&bytes, 0) ^ DICTIONARY_MAGIC_ID,
+            bytes.len() as u32
+        );
+        assert_eq!(read_u32(&bytes, 4), DIC_VERSION);
+        assert_eq!(read_u32(&bytes, 8), MECAB_USR_DIC);
+        assert_eq!(read_u32(&bytes, 12), 3);
+        assert_eq!((read_u32(&bytes, 16), read_u32(&bytes, 20)), (3, 3));
+        let (dsize, tsize, fsize) = (
+            read_u32(&bytes, 24) as usize,
+            read_u32(&bytes, 28) as usize,
+            read_u32(&bytes, 32) as usize,
+        );
+        assert_eq!(72 + dsize + tsize + fsize, bytes.len());
+        assert_eq!(tsize, 3 * 16);
+
+        let units = bytes[72..72 + dsize]
+            .chunks(8)
+            .map(|unit| (read_u32(unit, 0) as i32, read_u32(unit, 4)))
+            .collect::<Vec<_>>();
+        // Both 감스트 rows share one key: the first token and a count of two.
+        assert_eq!(exact_match(&units, "감스트".as_bytes()), Some(2));
+        assert_eq!(exact_match(&units, "뤣쉙퀡".as_bytes()), Some((2 << 8) | 1));
+
+        let token = |i: usize| &bytes[72 + dsize + i * 16..72 + dsize + (i + 1) * 16];
+        let wcost = |i: usize| i16::from_le_bytes([token(i)[6], token(i)[7]]);
+        // Context ids come from the rewrite rules, the missing cost from the system median.
+        assert_eq!(&token(0)[..4], &[2, 0, 2, 0]);
+        assert_eq!(wcost(0), 300);
+        assert_eq!(&token(1)[..4], &[1, 0, 1, 0]);
+        assert_eq!(wcost(1), 10);
+        assert_eq!(wcost(2), -500);
+        let features = &bytes[72 + dsize + tsize..];
+        let offset = read_u32(token(2), 8) as usize;
+        assert!(features[offset..].starts_with("NNP,*,T,뤣쉙퀡\0".as_bytes()));
+
+        std::fs::write(dir.join("user-dic/brand.csv"), "").unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+        assert_eq!(
+            compile_user_dic(&dir, dir.join("user-dic"), &out_path).unwrap(),
+            0
+        );
+        assert!(!out_path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
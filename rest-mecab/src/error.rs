@@ -0,0 +1,224 @@
+//! Errors both servers answer with. Each kind has a status and a stable `code`, and the body
+//! is JSON, `{"code": ..., "message": ..., "request_id": ...}`, so clients can tell bad input
+//! from server faults without parsing messages.
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Identifies a request in error bodies and logs. Taken from the request when a proxy in
+/// front already set it, generated otherwise, and echoed on every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request is malformed: a body that is not UTF-8, a bad query or an unknown format.
+    BadRequest(anyhow::Error),
+    /// The tenant, dictionary, entry or version the request names does not exist.
+    NotFound(anyhow::Error),
+    /// The server lacks a setting the request needs, such as `USERDIC_SERVER_URL`.
+    NotConfigured(anyhow::Error),
+    /// The userdic server failed, or what it answered was refused.
+    Upstream(anyhow::Error),
+    /// MeCab failed to parse the text.
+    Tokenize(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl Error {
+    pub fn bad_request<M: fmt::Display>(message: M) -> Self {
+        Error::BadRequest(anyhow::Error::msg(message.to_string()))
+    }
+    pub fn not_found<M: fmt::Display>(message: M) -> Self {
+        Error::NotFound(anyhow::Error::msg(message.to_string()))
+    }
+    pub fn not_configured<M: fmt::Display>(message: M) -> Self {
+        Error::NotConfigured(anyhow::Error::msg(message.to_string()))
+    }
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::BadRequest(_) => "bad_request",
+            Error::NotFound(_) => "not_found",
+            Error::NotConfigured(_) => "not_configured",
+            Error::Upstream(_) => "upstream_failed",
+            Error::Tokenize(_) => "tokenize_failed",
+            Error::Internal(_) => "internal",
+        }
+    }
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Error::BadRequest(err)
+            | Error::NotFound(err)
+            | Error::NotConfigured(err)
+            | Error::Upstream(err)
+            | Error::Tokenize(err)
+            | Error::Internal(err) => err,
+        }
+    }
+    fn response(&self, request_id: Option<&str>) -> HttpResponse {
+        error_response(
+            self.status_code(),
+            self.code(),
+            self.to_string(),
+            request_id,
+        )
+    }
+}
+
+fn error_response(
+    status: StatusCode,
+    code: &str,
+    message: String,
+    request_id: Option<&str>,
+) -> HttpResponse {
+    HttpResponse::build(status).json(ErrorBody {
+        code: code.to_string(),
+        message,
+        request_id: request_id.map(str::to_string),
+    })
+}
+
+/// The code of a failure actix answered on its own, such as a body over the payload limit:
+/// the status' reason in snake case for client errors, `internal` for server faults.
+fn status_code_name(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) if status.is_client_error() => reason
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>()
+            .join("_"),
+        _ => "internal".to_string(),
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.inner())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::NotConfigured(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Error::Tokenize(_) | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        self.response(None)
+    }
+}
+
+/// Anything not given a kind is a server fault.
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Error {
+        Error::Internal(err)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+/// Answers queries, paths and JSON bodies that fail to deserialize as bad requests.
+pub fn extractors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        web::QueryConfig::default()
+            .error_handler(|err, _| Error::bad_request(format!("invalid query: {}", err)).into()),
+    )
+    .app_data(
+        web::PathConfig::default()
+            .error_handler(|err, _| Error::bad_request(format!("invalid path: {}", err)).into()),
+    )
+    .app_data(
+        web::JsonConfig::default()
+            .error_handler(|err, _| Error::bad_request(format!("invalid JSON: {}", err)).into()),
+    );
+}
+
+/// The request's ID as a proxy set it, if it is short, visible ASCII.
+fn given_request_id(req: &ServiceRequest) -> Option<String> {
+    let id = req.headers().get(REQUEST_ID_HEADER)?.to_str().ok()?;
+    if !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_graphic()) {
+        Some(id.to_string())
+    } else {
+        None
+    }
+}
+
+/// Gives every request an ID, returns it in [`REQUEST_ID_HEADER`], and fills it into the body
+/// of an [`Error`]. Failures of actix itself get the same body, with a code after their
+/// status. Server faults are logged with it.
+pub struct RequestId;
+
+impl<S> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddleware { service })
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id =
+            given_request_id(&req).unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+        let res = self.service.call(req);
+        Box::pin(async move {
+            let mut res = res.await?;
+            let status = res.response().status();
+            let err = res
+                .response()
+                .error()
+                .map(|err| match err.as_error::<Error>() {
+                    Some(err) => (err.status_code(), err.code().to_string(), err.to_string()),
+                    None => (status, status_code_name(status), err.to_string()),
+                });
+            if let Some((status, code, message)) = err {
+                if status.is_server_error() {
+                    println!("ERROR: {}: {}: {}", request_id, code, message);
+                }
+                res = res.into_response(error_response(status, &code, message, Some(&request_id)));
+            }
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
@@ -1 +1,10 @@
+pub mod changes;
+pub mod dictionary;
+pub mod error;
+pub mod metrics;
+pub mod tag;
+pub mod tenant;
 pub mod tokenizer;
+pub mod userdics;
+pub mod validate;
+pub mod versions;
@@ -0,0 +1,217 @@
+//! Prometheus metrics of both servers, kept in the default registry and served at `/metrics`.
+//! Each is registered the first time it is used, so a server only exposes the ones it records.
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpRequest;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec, TextEncoder,
+};
+use std::time::Instant;
+
+/// Route label of requests no route matched, so unknown paths don't each get a series.
+const UNMATCHED: &str = "unmatched";
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Requests served, by method, route pattern and status.",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time to the response head, by method and route pattern.",
+        &["method", "route"]
+    )
+    .unwrap();
+    pub static ref TOKENIZED_BYTES: IntCounter = register_int_counter!(
+        "tokenizer_tokenized_bytes_total",
+        "Bytes of text tokenized, before normalization."
+    )
+    .unwrap();
+    pub static ref TOKENS: IntCounter =
+        register_int_counter!("tokenizer_tokens_total", "Tokens produced.").unwrap();
+    pub static ref DICTIONARY_RELOADS: IntCounterVec = register_int_counter_vec!(
+        "tokenizer_dictionary_reloads_total",
+        "Tokenizers reopened on a new user dictionary build, by result.",
+        &["result"]
+    )
+    .unwrap();
+    pub static ref DICTIONARY_RELOAD_DURATION: Histogram = register_histogram!(
+        "tokenizer_dictionary_reload_duration_seconds",
+        "Time to reopen a tokenizer on a new user dictionary build."
+    )
+    .unwrap();
+    pub static ref USERDIC_SYNCS: IntCounterVec = register_int_counter_vec!(
+        "tokenizer_userdic_syncs_total",
+        "Syncs of learned nouns from the userdic server, by tenant and result.",
+        &["tenant", "result"]
+    )
+    .unwrap();
+    pub static ref USERDIC_ENTRIES: IntGaugeVec = register_int_gauge_vec!(
+        "tokenizer_userdic_entries",
+        "Entries of the active user dictionary build, by tenant.",
+        &["tenant"]
+    )
+    .unwrap();
+    pub static ref LEARNED_NOUNS: IntGaugeVec = register_int_gauge_vec!(
+        "userdic_learned_nouns",
+        "Learned nouns, blocked ones included, as estimated by RocksDB, by tenant.",
+        &["tenant"]
+    )
+    .unwrap();
+    pub static ref ROCKSDB_SST_BYTES: IntGaugeVec = register_int_gauge_vec!(
+        "userdic_rocksdb_sst_bytes",
+        "Size of the SST files of a keyspace, by tenant and keyspace.",
+        &["tenant", "keyspace"]
+    )
+    .unwrap();
+    pub static ref ROCKSDB_MEMTABLE_BYTES: IntGaugeVec = register_int_gauge_vec!(
+        "userdic_rocksdb_memtable_bytes",
+        "Size of the memtables of a keyspace, by tenant and keyspace.",
+        &["tenant", "keyspace"]
+    )
+    .unwrap();
+    pub static ref TRAINED_BYTES: IntCounterVec = register_int_counter_vec!(
+        "userdic_trained_bytes_total",
+        "Bytes of text trained on, by tenant.",
+        &["tenant"]
+    )
+    .unwrap();
+    pub static ref TRAINED_LINES: IntCounterVec = register_int_counter_vec!(
+        "userdic_trained_lines_total",
+        "Lines of text trained on, by tenant.",
+        &["tenant"]
+    )
+    .unwrap();
+    pub static ref TRAIN_DURATION: HistogramVec = register_histogram_vec!(
+        "userdic_train_duration_seconds",
+        "Time to train on a request body, by tenant.",
+        &["tenant"]
+    )
+    .unwrap();
+}
+
+/// `ok` or `error`, the result label of the metrics that have one.
+pub fn result_label<T, E>(result: &Result<T, E>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    }
+}
+
+/// Every registered metric in the Prometheus text format.
+pub fn render() -> anyhow::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// The pattern of the route that served a request, such as `/t/{tenant}/tokenize`.
+fn route_of(req: &HttpRequest) -> String {
+    match req.match_pattern() {
+        Some(pattern) => without_regexes(&pattern),
+        None => UNMATCHED.to_string(),
+    }
+}
+
+/// Drops the regexes of a pattern's dynamic segments, `/t/{tenant:[a-z]{1,64}}` becoming
+/// `/t/{tenant}`.
+fn without_regexes(pattern: &str) -> String {
+    let mut route = String::with_capacity(pattern.len());
+    let mut depth = 0;
+    let mut in_regex = false;
+    for c in pattern.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 1 && !in_regex => {
+                in_regex = true;
+                continue;
+            }
+            _ => {}
+        }
+        if in_regex && depth == 0 {
+            in_regex = false;
+        }
+        if !in_regex {
+            route.push(c);
+        }
+    }
+    route
+}
+
+/// Counts and times every request by method and route pattern.
+pub struct Metrics;
+
+impl<S, B> Transform<S, ServiceRequest> for Metrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = MetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(MetricsMiddleware { service })
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let res = self.service.call(req);
+        Box::pin(async move {
+            let res = res.await?;
+            let method = res.request().method().to_string();
+            let route = route_of(res.request());
+            HTTP_REQUEST_DURATION
+                .with_label_values(&[&method, &route])
+                .observe(start.elapsed().as_secs_f64());
+            HTTP_REQUESTS
+                .with_label_values(&[&method, &route, res.status().as_str()])
+                .inc();
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_without_regexes() {
+        assert_eq!(without_regexes("/tokenize"), "/tokenize");
+        assert_eq!(
+            without_regexes("/userdics/{name}/entries/{surface}"),
+            "/userdics/{name}/entries/{surface}"
+        );
+        assert_eq!(
+            without_regexes(&format!("{}/tokenize", crate::tenant::SCOPE)),
+            "/t/{tenant}/tokenize"
+        );
+    }
+}
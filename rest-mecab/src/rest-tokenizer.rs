@@ -1,48 +1,513 @@
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
-use serde::Deserialize;
 use actix_files::Files;
+use actix_web::{
+    delete, get, post, put, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use serde::{Deserialize, Serialize};
 
+pub mod changes;
+pub mod dictionary;
+pub mod error;
+pub mod metrics;
+pub mod tag;
+pub mod tenant;
 pub mod tokenizer;
+pub mod userdics;
+pub mod validate;
+pub mod versions;
 
 use async_rwlock::RwLock;
-use tokenizer::Tokenizer;
+use error::Error;
+use hangul_normalize::NormalizeConfig;
+use tag::Tag;
+use tokenizer::{gen_userdic_async, Analytics, TagFilter, Tenants, TokenizerSlot};
+use userdics::{Entry, SyncStatus, UserDic, UserDics};
+use validate::Limits;
+use versions::Meta;
 
+use futures::StreamExt;
 use postage::prelude::{Sink, Stream};
+use rand::Rng;
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::rc::Rc;
 
-#[derive(Debug, derive_more::Display, derive_more::Error)]
-struct Error {
-    err: anyhow::Error,
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+/// Per-request overrides of the default normalization profile. `derepeat=0` turns derepeat
+/// off and `keep_control_chars=true` leaves unsupported chars untouched.
+#[derive(Debug, Default, Deserialize)]
+struct NormalizeQuery {
+    control_chars: Option<String>,
+    keep_control_chars: Option<bool>,
+    whitespace_less: Option<bool>,
+    derepeat: Option<usize>,
 }
-impl actix_web::error::ResponseError for Error {}
-impl From<anyhow::Error> for Error {
-    fn from(err: anyhow::Error) -> Error {
-        Error { err }
+impl NormalizeQuery {
+    fn config(&self) -> NormalizeConfig {
+        let mut config = NormalizeConfig::default();
+        if let Some(replacer) = &self.control_chars {
+            config.control_chars = Some(replacer.clone());
+        }
+        if self.keep_control_chars == Some(true) {
+            config.control_chars = None;
+        }
+        if let Some(whitespace_less) = self.whitespace_less {
+            config.whitespace_less = whitespace_less;
+        }
+        match self.derepeat {
+            Some(0) => config.derepeat = None,
+            Some(n) => config.derepeat = Some(n),
+            None => {}
+        }
+        config
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputShape {
+    Full,
+    Pairs,
+    Surface,
+    Lemma,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenTag {
+    token: String,
+    tag: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Output {
+    Full(Vec<Analytics>),
+    Pairs(Vec<TokenTag>),
+    Surface(Vec<String>),
+}
+
+/// Which tokens to return and in what shape: `include`/`exclude` take comma-separated tag
+/// patterns such as `NN*,V*`, `expand=true` splits compound tokens into their morphemes
+/// before filtering, and `output` is one of `full`, `pairs`, `surface` or `lemma`.
 #[derive(Debug, Deserialize)]
-struct SearchQuery {
-    q: String,
+struct ProjectionQuery {
+    include: Option<String>,
+    exclude: Option<String>,
+    expand: Option<bool>,
+    output: Option<OutputShape>,
+}
+impl ProjectionQuery {
+    fn projection(&self) -> Projection {
+        Projection {
+            filter: TagFilter::parse(self.include.as_deref(), self.exclude.as_deref()),
+            expand: self.expand.unwrap_or(false),
+            output: self.output.unwrap_or(OutputShape::Full),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Projection {
+    filter: TagFilter,
+    expand: bool,
+    output: OutputShape,
+}
+impl Projection {
+    fn apply(&self, analytics: Vec<Analytics>) -> Output {
+        let analytics = if self.expand {
+            analytics.into_iter().flat_map(Analytics::expand).collect()
+        } else {
+            analytics
+        };
+        let analytics = analytics
+            .into_iter()
+            .filter(|analytics| self.filter.matches(analytics));
+        match self.output {
+            OutputShape::Full => Output::Full(analytics.collect()),
+            OutputShape::Pairs => Output::Pairs(
+                analytics
+                    .map(|analytics| TokenTag {
+                        tag: analytics
+                            .tags
+                            .iter()
+                            .map(Tag::as_str)
+                            .collect::<Vec<_>>()
+                            .join("+"),
+                        token: analytics.token,
+                    })
+                    .collect(),
+            ),
+            OutputShape::Surface => {
+                Output::Surface(analytics.map(|analytics| analytics.token).collect())
+            }
+            OutputShape::Lemma => {
+                Output::Surface(analytics.map(|analytics| analytics.lemma).collect())
+            }
+        }
+    }
+}
+
+/// Largest `n` accepted for N-best analysis.
+const MAX_NBEST: usize = 32;
+
+/// `n` switches `/tokenize` to N-best mode, returning up to `n` ranked alternatives.
+#[derive(Debug, Deserialize)]
+struct NBestQuery {
+    n: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct RankedOutput {
+    cost: i64,
+    analytics: Output,
+}
+
+async fn tokenize_response(
+    q: &str,
+    normalize: &NormalizeQuery,
+    projection: &ProjectionQuery,
+    nbest: &NBestQuery,
+    tokenizer: &TokenizerSlot,
+) -> Result<HttpResponse, Error> {
+    let projection = projection.projection();
+    match nbest.n {
+        Some(n) => {
+            let candidates = tokenizer
+                .get()
+                .tokenize_nbest(q, n.min(MAX_NBEST), &normalize.config())
+                .map_err(Error::Tokenize)?;
+            Ok(HttpResponse::Ok().json(
+                candidates
+                    .into_iter()
+                    .map(|candidate| RankedOutput {
+                        cost: candidate.cost,
+                        analytics: projection.apply(candidate.analytics),
+                    })
+                    .collect::<Vec<_>>(),
+            ))
+        }
+        None => {
+            let result = tokenizer
+                .get()
+                .tokenize_with(q, &normalize.config())
+                .map_err(Error::Tokenize)?;
+            Ok(HttpResponse::Ok().json(projection.apply(result)))
+        }
+    }
 }
 
 #[get("/tokenize")]
 async fn tokenize(
     q: web::Query<SearchQuery>,
-    tokenizer: web::Data<RwLock<Tokenizer>>,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    nbest: web::Query<NBestQuery>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
 ) -> Result<HttpResponse, Error> {
-    let q = q.into_inner().q;
-    let result = tokenizer.read().await.tokenize(&q)?;
-    Ok(HttpResponse::Ok().json(result))
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    tokenize_response(&q.q, &normalize, &projection, &nbest, &tokenizer).await
 }
 
 #[post("/tokenize")]
 async fn tokenize_post(
     bytes: web::Bytes,
-    tokenizer: web::Data<RwLock<Tokenizer>>,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    nbest: web::Query<NBestQuery>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let q = String::from_utf8(bytes.to_vec())
+        .map_err(|err| Error::bad_request(format!("body is not UTF-8: {}", err)))?;
+    tokenize_response(&q, &normalize, &projection, &nbest, &tokenizer).await
+}
+
+/// The token stream in dictionary form; accepts the same options as `/tokenize` except
+/// `output`.
+#[get("/lemmatize")]
+async fn lemmatize(
+    q: web::Query<SearchQuery>,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let q = q.into_inner().q;
+    let result = tokenizer
+        .get()
+        .tokenize_with(&q, &normalize.config())
+        .map_err(Error::Tokenize)?;
+    let projection = Projection {
+        output: OutputShape::Lemma,
+        ..projection.projection()
+    };
+    Ok(HttpResponse::Ok().json(projection.apply(result)))
+}
+
+#[post("/lemmatize")]
+async fn lemmatize_post(
+    bytes: web::Bytes,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let q = String::from_utf8(bytes.to_vec())
+        .map_err(|err| Error::bad_request(format!("body is not UTF-8: {}", err)))?;
+    let result = tokenizer
+        .get()
+        .tokenize_with(&q, &normalize.config())
+        .map_err(Error::Tokenize)?;
+    let projection = Projection {
+        output: OutputShape::Lemma,
+        ..projection.projection()
+    };
+    Ok(HttpResponse::Ok().json(projection.apply(result)))
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchDocument {
+    id: String,
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResult {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    analytics: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+impl BatchResult {
+    fn new(id: String, result: anyhow::Result<Vec<Analytics>>, projection: &Projection) -> Self {
+        match result {
+            Ok(analytics) => Self {
+                id,
+                analytics: Some(projection.apply(analytics)),
+                error: None,
+            },
+            Err(err) => Self {
+                id,
+                analytics: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+/// A single document handed to whichever worker's `Tokenizer` picks it up first.
+struct BatchJob {
+    tenant: String,
+    text: String,
+    normalize: NormalizeConfig,
+    result_tx: postage::oneshot::Sender<anyhow::Result<Vec<Analytics>>>,
+}
+
+/// Tokenizes a batch document on the worker that picked it up.
+fn run_batch_job(tenants: &Tenants, job: &BatchJob) -> anyhow::Result<Vec<Analytics>> {
+    match tenants.get(&job.tenant) {
+        Some(tokenizer) => tokenizer.get().tokenize_with(&job.text, &job.normalize),
+        None => Err(anyhow::Error::msg(format!(
+            "no tenant named {}",
+            job.tenant
+        ))),
+    }
+}
+
+fn parse_batch(req: &HttpRequest, bytes: &[u8]) -> anyhow::Result<Vec<BatchDocument>> {
+    if req.content_type() == "application/x-ndjson" {
+        std::str::from_utf8(bytes)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    } else {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[post("/tokenize/batch")]
+async fn tokenize_batch(
+    req: HttpRequest,
+    bytes: web::Bytes,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    tenants: web::Data<Tenants>,
+    batch_tx: web::Data<postage::dispatch::Sender<BatchJob>>,
+) -> Result<HttpResponse, Error> {
+    let documents = parse_batch(&req, &bytes).map_err(Error::BadRequest)?;
+    let tenant = tenant::of(&req);
+    tenant_tokenizer(&tenants, &tenant)?;
+    let normalize = normalize.config();
+    let projection = projection.projection();
+    let jobs = documents.into_iter().map(|document| {
+        let mut batch_tx = batch_tx.get_ref().clone();
+        let tenant = tenant.clone();
+        let normalize = normalize.clone();
+        let projection = &projection;
+        async move {
+            let (result_tx, mut result_rx) = postage::oneshot::channel();
+            let job = BatchJob {
+                tenant,
+                text: document.text,
+                normalize,
+                result_tx,
+            };
+            let result = match batch_tx.send(job).await {
+                Ok(()) => result_rx
+                    .recv()
+                    .await
+                    .unwrap_or_else(|| Err(anyhow::Error::msg("tokenizer worker dropped the job"))),
+                Err(_) => Err(anyhow::Error::msg("no tokenizer worker is available")),
+            };
+            BatchResult::new(document.id, result, projection)
+        }
+    });
+    Ok(HttpResponse::Ok().json(futures::future::join_all(jobs).await))
+}
+
+/// Largest request body accepted, and largest line of a streamed body.
+const MAX_PAYLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+fn decode_line(mut line: Vec<u8>) -> anyhow::Result<String> {
+    while matches!(line.last(), Some(b'\n') | Some(b'\r')) {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|err| anyhow::anyhow!("line is not UTF-8: {}", err))
+}
+
+/// Cuts a body into lines as its chunks arrive. A line longer than `max_bytes` is reported
+/// once and the rest of it is dropped, so no more than a line and a chunk are ever held.
+struct LineSplitter {
+    buf: Vec<u8>,
+    max_bytes: usize,
+    /// Inside a line already reported as too long.
+    skipping: bool,
+}
+impl LineSplitter {
+    fn new(max_bytes: usize) -> Self {
+        LineSplitter {
+            buf: Vec::new(),
+            max_bytes,
+            skipping: false,
+        }
+    }
+    fn too_long(&mut self) -> anyhow::Result<String> {
+        self.buf.clear();
+        Err(anyhow::anyhow!(
+            "line is longer than {} bytes",
+            self.max_bytes
+        ))
+    }
+    fn push(&mut self, mut chunk: &[u8], lines: &mut VecDeque<anyhow::Result<String>>) {
+        while !chunk.is_empty() {
+            let end = chunk.iter().position(|b| *b == b'\n');
+            let (part, rest) = match end {
+                Some(i) => (&chunk[..i], &chunk[i + 1..]),
+                None => (chunk, &[][..]),
+            };
+            if !self.skipping {
+                self.buf.extend_from_slice(part);
+                if self.buf.len() > self.max_bytes {
+                    lines.push_back(self.too_long());
+                    self.skipping = true;
+                }
+            }
+            if end.is_some() {
+                if !self.skipping {
+                    lines.push_back(decode_line(std::mem::take(&mut self.buf)));
+                }
+                self.skipping = false;
+            }
+            chunk = rest;
+        }
+    }
+    /// The last line, if the body doesn't end with a newline.
+    fn finish(&mut self) -> Option<anyhow::Result<String>> {
+        if self.skipping || self.buf.is_empty() {
+            None
+        } else {
+            Some(decode_line(std::mem::take(&mut self.buf)))
+        }
+    }
+}
+
+/// Splits a request body into lines as the chunks arrive, so only the current line is held in
+/// memory and the body is only read as fast as the lines are consumed. A line that is too long
+/// or not UTF-8 comes out as an error and the lines after it still follow; a failure to read
+/// the body is the last item.
+fn payload_lines(payload: web::Payload) -> impl futures::Stream<Item = anyhow::Result<String>> {
+    futures::stream::unfold(
+        (
+            payload,
+            LineSplitter::new(MAX_PAYLOAD_BYTES),
+            VecDeque::new(),
+            false,
+        ),
+        |(mut payload, mut splitter, mut lines, mut eof)| async move {
+            loop {
+                if let Some(line) = lines.pop_front() {
+                    return Some((line, (payload, splitter, lines, eof)));
+                }
+                if eof {
+                    return None;
+                }
+                match payload.next().await {
+                    Some(Ok(chunk)) => splitter.push(&chunk, &mut lines),
+                    Some(Err(err)) => {
+                        lines.push_back(Err(anyhow::anyhow!("failed to read the body: {}", err)));
+                        eof = true;
+                    }
+                    None => {
+                        lines.extend(splitter.finish());
+                        eof = true;
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Tokenizes the body line by line and writes one NDJSON record per input line while the
+/// rest of the body is still arriving. A line that is too long, is not UTF-8 or fails to
+/// tokenize becomes an `{"error": ...}` record so the output stays aligned with the input.
+#[post("/tokenize/stream")]
+async fn tokenize_stream(
+    payload: web::Payload,
+    normalize: web::Query<NormalizeQuery>,
+    projection: web::Query<ProjectionQuery>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
 ) -> Result<HttpResponse, Error> {
-    let q = String::from_utf8(bytes.to_vec()).map_err(anyhow::Error::from)?;
-    let result = tokenizer.read().await.tokenize(&q)?;
-    Ok(HttpResponse::Ok().json(result))
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let normalize = normalize.config();
+    let projection = projection.projection();
+    let records = payload_lines(payload).then(move |line| {
+        let tokenizer = tokenizer.clone();
+        let normalize = normalize.clone();
+        let projection = projection.clone();
+        async move {
+            let result = line.and_then(|line| tokenizer.get().tokenize_with(&line, &normalize));
+            let mut record = match result {
+                Ok(analytics) => serde_json::to_vec(&projection.apply(analytics)),
+                Err(err) => serde_json::to_vec(&serde_json::json!({ "error": err.to_string() })),
+            }
+            .map_err(anyhow::Error::from)?;
+            record.push(b'\n');
+            Ok::<_, Error>(web::Bytes::from(record))
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(Box::pin(records)))
 }
 
 #[get("/health")]
@@ -50,48 +515,678 @@ async fn health() -> impl Responder {
     "ok"
 }
 
+#[get("/metrics")]
+async fn export_metrics(tenants: web::Data<Tenants>) -> Result<HttpResponse, Error> {
+    for (tenant, entries) in tenants.userdic_entries()? {
+        metrics::USERDIC_ENTRIES
+            .with_label_values(&[&tenant])
+            .set(entries as i64);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics::render()?))
+}
+
+#[derive(Deserialize)]
+struct UserdicQuery {
+    /// `json`, the default, or `csv`.
+    format: Option<String>,
+}
+
+/// Entries of the active user dictionary build, as parsed entries or as the CSV it was
+/// compiled from.
 #[get("/userdic")]
 async fn userdic(
-    tokenizer: web::Data<RwLock<Tokenizer>>,
+    req: HttpRequest,
+    query: web::Query<UserdicQuery>,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let lines = tokenizer.get().userdic_entries()?;
+    Ok(match query.format.as_deref() {
+        None | Some("json") => HttpResponse::Ok().json(
+            lines
+                .iter()
+                .map(|line| Entry::parse(line))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        Some("csv") => {
+            let mut csv = lines.join("\n");
+            csv.push('\n');
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .body(csv)
+        }
+        Some(format) => return Err(Error::bad_request(format!("unknown format: {}", format))),
+    })
+}
+
+/// What a tokenizer serves for a tenant, and how its last sync went.
+#[derive(Serialize)]
+struct UserdicStatus {
+    tenant: String,
+    /// The active build, none before the first one.
+    version: Option<u64>,
+    created_at: Option<u64>,
+    entries: usize,
+    hash: Option<String>,
+    /// Where the learned nouns are synced from.
+    upstream: Option<String>,
+    learned_seq: Option<u64>,
+    last_sync: Option<SyncStatus>,
+}
+
+#[get("/userdic/status")]
+async fn userdic_status(
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
 ) -> Result<HttpResponse, Error> {
-    let res: Vec<String> = serde_json::from_slice(tokenizer.read().await.get_userdic()?.as_ref()).unwrap();
-    Ok(HttpResponse::Ok().json(res))
+    let tenant = tenant::of(&req);
+    let tokenizer = tenant_tokenizer(&tenants, &tenant)?.get();
+    let versions = tokenizer.versions();
+    let meta = match versions.current()? {
+        Some(version) => Some(versions.meta(version)?),
+        None => None,
+    };
+    let hash = match &meta {
+        Some(Meta {
+            hash: Some(hash), ..
+        }) => Some(hash.clone()),
+        Some(meta) => Some(versions.hash(meta.version)?),
+        None => None,
+    };
+    let userdics = tokenizer.userdics();
+    Ok(HttpResponse::Ok().json(UserdicStatus {
+        upstream: std::env::var("USERDIC_SERVER_URL")
+            .ok()
+            .map(|url| tenant_nouns_url(&url, &tenant)),
+        tenant,
+        version: meta.as_ref().map(|meta| meta.version),
+        created_at: meta.as_ref().map(|meta| meta.created_at),
+        entries: meta.as_ref().map_or(0, |meta| meta.entries),
+        hash,
+        learned_seq: userdics.learned_seq()?,
+        last_sync: userdics.last_sync()?,
+    }))
 }
 
 #[post("/sync-userdic")]
 async fn sync_userdic(
-    tokenizer: web::Data<RwLock<Tokenizer>>,
-    reload_tx: web::Data<RwLock<postage::broadcast::Sender<()>>>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
 ) -> Result<String, Error> {
+    let tenant = tenant::of(&req);
+    let tokenizer = tenants.create(&tenant)?.get();
     let userdic_server_url = std::env::var("USERDIC_SERVER_URL")
-        .map_err(|_| anyhow::Error::msg("USERDIC_SERVER_URL"))?;
-    let client = awc::Client::builder().timeout(std::time::Duration::from_secs(3600)).finish();
-    let res = client
-        .get(&userdic_server_url)
+        .map_err(|_| Error::not_configured("USERDIC_SERVER_URL is not set"))?;
+    let userdic_server_url = tenant_nouns_url(&userdic_server_url, &tenant);
+    let client = awc::Client::builder()
         .timeout(std::time::Duration::from_secs(3600))
+        .finish();
+    if sync_nouns(
+        &client,
+        tokenizer.mecab_dic_path(),
+        tokenizer.userdic_path(),
+        &tenant,
+        &userdic_server_url,
+    )
+    .await?
+    {
+        reload_tx
+            .write()
+            .await
+            .send(tenant)
+            .await
+            .map_err(anyhow::Error::from)?;
+    }
+    //tokenizer.write().await.reload();
+    Ok("".to_string())
+}
+
+/// Tenants with a sync scheduled by a notice and not started yet, shared by all workers.
+type PendingSyncs = std::sync::Mutex<HashSet<String>>;
+
+/// Up to `USERDIC_NOTIFY_JITTER_SECONDS`, so replicas notified together don't all pull from
+/// the userdic server at once.
+fn notify_jitter() -> anyhow::Result<std::time::Duration> {
+    let max_seconds: u64 = std::env::var("USERDIC_NOTIFY_JITTER_SECONDS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()?;
+    Ok(std::time::Duration::from_millis(
+        rand::thread_rng().gen_range(0..=max_seconds * 1000),
+    ))
+}
+
+/// Told by the userdic server that a tenant's nouns changed. Schedules a sync after a random
+/// delay unless the tokenizer has already synced that far or a sync is scheduled already.
+/// Tenants this replica does not have are 404, a notice creates none.
+#[post("/userdic/notify")]
+async fn notify_userdic(
+    req: HttpRequest,
+    notice: web::Json<changes::Notice>,
+    tenants: web::Data<Tenants>,
+    pending: web::Data<PendingSyncs>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let tokenizer = tenant_tokenizer(&tenants, &tenant)?.get();
+    if tokenizer.userdics().learned_seq()? >= Some(notice.seq) {
+        return Ok(HttpResponse::NoContent().finish());
+    }
+    let userdic_server_url = std::env::var("USERDIC_SERVER_URL")
+        .map_err(|_| Error::not_configured("USERDIC_SERVER_URL is not set"))?;
+    let delay = notify_jitter()?;
+    if !pending
+        .lock()
+        .map_err(|_| anyhow::Error::msg("pending syncs poisoned"))?
+        .insert(tenant.clone())
+    {
+        return Ok(HttpResponse::Accepted().finish());
+    }
+    let url = tenant_nouns_url(&userdic_server_url, &tenant);
+    let (pending, reload_tx) = (pending.clone(), reload_tx.clone());
+    let mecab_dic_path = tokenizer.mecab_dic_path().to_path_buf();
+    let userdic_path = tokenizer.userdic_path().to_path_buf();
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(delay).await;
+        // Notices from here on schedule another sync, in case this one misses their changes.
+        if let Ok(mut pending) = pending.lock() {
+            pending.remove(&tenant);
+        }
+        let client = awc::Client::builder()
+            .timeout(std::time::Duration::from_secs(3600))
+            .finish();
+        match sync_nouns(&client, &mecab_dic_path, &userdic_path, &tenant, &url).await {
+            Ok(true) => {
+                if let Err(err) = reload_tx.write().await.send(tenant.clone()).await {
+                    println!("ERROR: {}: {}", tenant, err);
+                }
+            }
+            Ok(false) => {}
+            Err(err) => println!("ERROR: {}: {}", tenant, err),
+        }
+    });
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Subscribes this tokenizer to the userdic server's notices, under the URL it is reachable
+/// at. The subscription is renewed on every periodic sync, since the userdic server drops
+/// subscribers it fails to reach several times in a row.
+async fn subscribe(client: &awc::Client, nouns_url: &str, own_url: &str) -> anyhow::Result<()> {
+    let url = match nouns_url.rfind('/') {
+        Some(i) => format!("{}/subscribers", &nouns_url[..i]),
+        None => {
+            return Err(anyhow::Error::msg(format!(
+                "bad userdic URL: {}",
+                nouns_url
+            )))
+        }
+    };
+    let res = client
+        .put(&url)
+        .query(&[("url", own_url)])?
         .send()
         .await
-        .unwrap()
-        .body()
-        .limit(1024 * 1024 * 1024)
+        .map_err(|_| anyhow::Error::msg("userdic server request fail"))?;
+    if !res.status().is_success() {
+        return Err(anyhow::Error::msg(format!("subscribe: {}", res.status())));
+    }
+    Ok(())
+}
+
+/// Changes logged after `since`, fetched page by page from `{nouns_url}/changes`. `None` when
+/// they can't be had: the server keeps no change log, or it lost changes already applied here.
+async fn pull_changes(
+    client: &awc::Client,
+    nouns_url: &str,
+    since: u64,
+    limits: &Limits,
+) -> anyhow::Result<Option<Vec<changes::Change>>> {
+    let mut pulled = Vec::new();
+    let mut after = since;
+    loop {
+        let mut res = client
+            .get(format!("{}/changes?since={}", nouns_url, after))
+            .send()
+            .await
+            .map_err(|_| anyhow::Error::msg("userdic server request fail"))?;
+        if !res.status().is_success() {
+            return Ok(None);
+        }
+        let page: changes::Page = res.json().limit(limits.max_bytes).await?;
+        // A log that went back, or dropped changes not pulled yet, can't be caught up with.
+        if page.last < since || after + 1 < page.first {
+            return Ok(None);
+        }
+        after = page.changes.last().map_or(page.last, |change| change.seq);
+        pulled.extend(page.changes);
+        if !page.more {
+            return Ok(Some(pulled));
+        }
+    }
+}
+
+/// The learned nouns after what changed since the last sync, and the sequence they reflect.
+/// Falls back to every noun when the last sync is unknown or the changes can't be had, and
+/// gives `None` when nothing changed. An empty full list is taken for a server that has not
+/// learned anything yet and is ignored too.
+async fn pull_nouns(
+    client: &awc::Client,
+    nouns_url: &str,
+    userdics: &UserDics,
+    limits: &Limits,
+) -> anyhow::Result<Option<(Vec<String>, Option<u64>)>> {
+    if let Some(since) = userdics.learned_seq()? {
+        if let Some(pulled) = pull_changes(client, nouns_url, since, limits).await? {
+            return Ok(match pulled.last() {
+                Some(last) => Some((
+                    changes::apply(userdics.learned_words()?, &pulled),
+                    Some(last.seq),
+                )),
+                None => None,
+            });
+        }
+    }
+    let mut res = client
+        .get(nouns_url)
+        .send()
         .await
-        .unwrap()
-        .to_vec();
-    let nouns: Vec<String> = serde_json::from_slice(&res).unwrap();
-    tokenizer
-        .read()
+        .map_err(|_| anyhow::Error::msg("userdic server request fail"))?;
+    if !res.status().is_success() {
+        return Err(anyhow::Error::msg(format!(
+            "userdic server responded {}",
+            res.status()
+        )));
+    }
+    let seq = res
+        .headers()
+        .get(changes::SEQ_HEADER)
+        .and_then(|seq| seq.to_str().ok())
+        .and_then(|seq| seq.parse().ok());
+    let res = res.body().limit(limits.max_bytes).await?;
+    let nouns: Vec<String> = serde_json::from_slice(&res)
+        .map_err(|err| anyhow::Error::msg(format!("learned nouns: {}", err)))?;
+    Ok(if nouns.is_empty() {
+        None
+    } else {
+        Some((nouns, seq))
+    })
+}
+
+/// `USERDIC_MAX_BYTES` and `USERDIC_MAX_ENTRIES`, where set.
+fn sync_limits() -> anyhow::Result<Limits> {
+    let mut limits = Limits::default();
+    if let Ok(max_bytes) = std::env::var("USERDIC_MAX_BYTES") {
+        limits.max_bytes = max_bytes.parse()?;
+    }
+    if let Ok(max_entries) = std::env::var("USERDIC_MAX_ENTRIES") {
+        limits.max_entries = max_entries.parse()?;
+    }
+    Ok(limits)
+}
+
+/// Builds a new user dictionary version from the learned nouns at `nouns_url`. Returns false
+/// when nothing changed since the last sync, so there is nothing to reload. Nouns that fail
+/// the gate in [`validate`] refuse the sync, as does a build that fails its smoke test, and the
+/// current version stays active. The outcome is recorded for `/userdic/status`. Failing to
+/// pull the nouns or refusing them is an upstream failure, failing to build them is ours.
+async fn sync_nouns(
+    client: &awc::Client,
+    mecab_dic_path: &Path,
+    userdic_path: &Path,
+    tenant: &str,
+    nouns_url: &str,
+) -> Result<bool, Error> {
+    let userdics = UserDics::new(userdic_path);
+    let mut rejected = 0;
+    let synced: Result<bool, Error> = async {
+        let limits = sync_limits()?;
+        let pulled = pull_nouns(client, nouns_url, &userdics, &limits)
+            .await
+            .map_err(Error::Upstream)?;
+        let (nouns, seq) = match pulled {
+            Some(pulled) => pulled,
+            None => return Ok(false),
+        };
+        let (nouns, dropped) = validate::check_nouns(nouns, &limits).map_err(Error::Upstream)?;
+        rejected = dropped;
+        gen_userdic_async(
+            mecab_dic_path,
+            userdic_path,
+            nouns,
+            seq,
+            Some(nouns_url.to_string()),
+        )
+        .await?;
+        Ok(true)
+    }
+    .await;
+    metrics::USERDIC_SYNCS
+        .with_label_values(&[tenant, metrics::result_label(&synced)])
+        .inc();
+    userdics.set_last_sync(&SyncStatus {
+        at: versions::now(),
+        source: nouns_url.to_string(),
+        ok: synced.is_ok(),
+        changed: matches!(synced, Ok(true)),
+        rejected,
+        error: synced.as_ref().err().map(|err| err.to_string()),
+    })?;
+    synced
+}
+
+async fn rebuild_userdic(
+    tokenizer: &TokenizerSlot,
+    tenant: String,
+    reload_tx: &RwLock<postage::broadcast::Sender<String>>,
+) -> Result<(), Error> {
+    tokenizer.get().rebuild_userdic_async().await?;
+    reload_tx
+        .write()
         .await
-        .gen_userdic_async(nouns)
+        .send(tenant)
         .await
         .map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+#[get("/userdics")]
+async fn list_userdics(
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    Ok(HttpResponse::Ok().json(tokenizer.get().userdics().list()?))
+}
+
+/// Path parameters are taken by name, since tenant-scoped routes carry the tenant as well.
+#[derive(Debug, Deserialize)]
+struct NamePath {
+    name: String,
+}
+
+#[get("/userdics/{name}")]
+async fn get_named_userdic(
+    path: web::Path<NamePath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    match tokenizer.get().userdics().get(&path.name)? {
+        Some(dic) => Ok(HttpResponse::Ok().json(dic)),
+        None => Err(Error::not_found(format!(
+            "no dictionary named {}",
+            path.name
+        ))),
+    }
+}
+
+/// Creates or replaces a named dictionary and recompiles the MeCab dictionary.
+#[put("/userdics/{name}")]
+async fn put_named_userdic(
+    path: web::Path<NamePath>,
+    dic: web::Json<UserDic>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let name = path.into_inner().name;
+    if !userdics::is_valid_name(&name) {
+        return Err(Error::bad_request(format!(
+            "invalid dictionary name: {}",
+            name
+        )));
+    }
+    let mut dic = dic.into_inner();
+    dic.name = name;
+    if !dic.pos.is_known() {
+        return Err(Error::bad_request(format!("unknown POS: {}", dic.pos)));
+    }
+    let invalid = dic.invalid_words();
+    if !invalid.is_empty() {
+        return Err(Error::bad_request(format!("invalid words: {:?}", invalid)));
+    }
+    let tokenizer = tenants.create(&tenant)?;
+    let userdics = tokenizer.get().userdics();
+    let previous = userdics.get(&dic.name)?;
+    let created = previous.is_none();
+    userdics.put(dic.clone())?;
+    if let Err(err) = rebuild_userdic(&tokenizer, tenant, &reload_tx).await {
+        // Left in place, a dictionary that fails to build would fail every later build too.
+        match previous {
+            Some(previous) => userdics.put(previous)?,
+            None => userdics.delete(&dic.name).map(|_| ())?,
+        }
+        return Err(err);
+    }
+    Ok(if created {
+        HttpResponse::Created().json(dic)
+    } else {
+        HttpResponse::Ok().json(dic)
+    })
+}
+
+#[delete("/userdics/{name}")]
+async fn delete_named_userdic(
+    path: web::Path<NamePath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let tokenizer = tenant_tokenizer(&tenants, &tenant)?;
+    if !tokenizer.get().userdics().delete(&path.name)? {
+        return Err(Error::not_found(format!(
+            "no dictionary named {}",
+            path.name
+        )));
+    }
+    rebuild_userdic(&tokenizer, tenant, &reload_tx).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/userdic/entries")]
+async fn list_entries(
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    Ok(HttpResponse::Ok().json(tokenizer.get().userdics().entries()?))
+}
+
+#[derive(Debug, Deserialize)]
+struct SurfacePath {
+    surface: String,
+}
+
+#[get("/userdic/entries/{surface}")]
+async fn get_entry(
+    path: web::Path<SurfacePath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    match tokenizer.get().userdics().entry(&path.surface)? {
+        Some(entry) => Ok(HttpResponse::Ok().json(entry)),
+        None => Err(Error::not_found(format!("no entry for {}", path.surface))),
+    }
+}
+
+/// Adds or replaces a hand-edited entry. It takes precedence over named dictionaries and
+/// learned nouns with the same surface.
+#[put("/userdic/entries/{surface}")]
+async fn put_entry(
+    path: web::Path<SurfacePath>,
+    entry: web::Json<Entry>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let mut entry = entry.into_inner();
+    entry.surface = path.into_inner().surface;
+    entry.check().map_err(Error::BadRequest)?;
+    let tokenizer = tenants.create(&tenant)?;
+    let userdics = tokenizer.get().userdics();
+    let previous = userdics.entry(&entry.surface)?;
+    let created = userdics.put_entry(entry.clone())?;
+    if let Err(err) = rebuild_userdic(&tokenizer, tenant, &reload_tx).await {
+        // Left in place, an entry that fails to build would fail every later build too.
+        match previous {
+            Some(previous) => userdics.put_entry(previous).map(|_| ())?,
+            None => userdics.delete_entry(&entry.surface).map(|_| ())?,
+        }
+        return Err(err);
+    }
+    Ok(if created {
+        HttpResponse::Created().json(entry)
+    } else {
+        HttpResponse::Ok().json(entry)
+    })
+}
+
+#[delete("/userdic/entries/{surface}")]
+async fn delete_entry(
+    path: web::Path<SurfacePath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let tokenizer = tenant_tokenizer(&tenants, &tenant)?;
+    if !tokenizer.get().userdics().delete_entry(&path.surface)? {
+        return Err(Error::not_found(format!("no entry for {}", path.surface)));
+    }
+    rebuild_userdic(&tokenizer, tenant, &reload_tx).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Serialize)]
+struct VersionsOutput {
+    current: Option<u64>,
+    versions: Vec<Meta>,
+}
+
+/// Every build, unless `USERDIC_KEEP_VERSIONS` limits how many are kept.
+#[get("/userdic/versions")]
+async fn list_versions(
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let versions = tokenizer.get().versions();
+    Ok(HttpResponse::Ok().json(VersionsOutput {
+        current: versions.current()?,
+        versions: versions.metas()?,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DiffPath {
+    from: u64,
+    to: u64,
+}
+
+/// Surfaces added, removed or changed going from the first version to the second.
+#[get("/userdic/versions/{from}/diff/{to}")]
+async fn diff_versions(
+    path: web::Path<DiffPath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+) -> Result<HttpResponse, Error> {
+    let tokenizer = tenant_tokenizer(&tenants, &tenant::of(&req))?;
+    let DiffPath { from, to } = path.into_inner();
+    let versions = tokenizer.get().versions();
+    for version in [from, to].iter() {
+        if !versions.exists(*version) {
+            return Err(Error::not_found(format!("no version {}", version)));
+        }
+    }
+    Ok(HttpResponse::Ok().json(versions.diff(from, to)?))
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionPath {
+    version: u64,
+}
+
+/// Makes an earlier build active again, together with the named dictionaries, manual entries
+/// and learned nouns it was built from.
+#[post("/userdic/versions/{version}/rollback")]
+async fn rollback_version(
+    path: web::Path<VersionPath>,
+    req: HttpRequest,
+    tenants: web::Data<Tenants>,
+    reload_tx: web::Data<RwLock<postage::broadcast::Sender<String>>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let tokenizer = tenant_tokenizer(&tenants, &tenant)?;
+    let version = path.version;
+    let versions = tokenizer.get().versions();
+    if !versions.exists(version) {
+        return Err(Error::not_found(format!("no version {}", version)));
+    }
+    if !versions.sources_dir(version).exists() {
+        return Err(Error::bad_request(format!(
+            "version {} was built before its sources were kept and can't be restored",
+            version
+        )));
+    }
+    let meta = tokenizer.get().rollback_userdic_async(version).await?;
     reload_tx
         .write()
         .await
-        .send(())
+        .send(tenant)
         .await
         .map_err(anyhow::Error::from)?;
-    //tokenizer.write().await.reload();
-    Ok("".to_string())
+    Ok(HttpResponse::Ok().json(meta))
+}
+
+/// The tokenizer of a tenant that exists. Only writes create a tenant, so reading from an
+/// unknown one is a 404 rather than an answer from the base dictionary.
+fn tenant_tokenizer(tenants: &Tenants, tenant: &str) -> Result<Rc<TokenizerSlot>, Error> {
+    tenants
+        .get(tenant)
+        .ok_or_else(|| Error::not_found(format!("no tenant named {}", tenant)))
+}
+
+/// The userdic server's nouns route for a tenant: `http://host/nouns` becomes
+/// `http://host/t/{tenant}/nouns`.
+fn tenant_nouns_url(url: &str, tenant: &str) -> String {
+    if tenant == tenant::DEFAULT {
+        return url.to_string();
+    }
+    match url.rfind('/') {
+        Some(i) => format!("{}/t/{}{}", &url[..i], tenant, &url[i..]),
+        None => url.to_string(),
+    }
+}
+
+/// Everything served per tenant, mounted at the root and under [`tenant::SCOPE`].
+fn tenant_services(cfg: &mut web::ServiceConfig) {
+    cfg.service(tokenize)
+        .service(sync_userdic)
+        .service(tokenize_post)
+        .service(tokenize_batch)
+        .service(tokenize_stream)
+        .service(lemmatize)
+        .service(lemmatize_post)
+        .service(userdic)
+        .service(userdic_status)
+        .service(list_userdics)
+        .service(get_named_userdic)
+        .service(put_named_userdic)
+        .service(delete_named_userdic)
+        .service(list_entries)
+        .service(get_entry)
+        .service(put_entry)
+        .service(delete_entry)
+        .service(list_versions)
+        .service(diff_versions)
+        .service(rollback_version)
+        .service(notify_userdic);
 }
 
 #[actix_web::main]
@@ -101,37 +1196,52 @@ async fn main() -> anyhow::Result<()> {
     let userdic_sync_interval_seconds: u64 = std::env::var("USERDIC_SYNC_INTERVAL_SECONDS")
         .unwrap_or_else(|_| "86400".to_string())
         .parse()?;
+    // Where the userdic server can reach this replica, to be notified of new nouns.
+    let userdic_subscribe_url = std::env::var("USERDIC_SUBSCRIBE_URL").ok();
     let mecab_dic_path =
         std::env::var("MECAB_DIC_PATH").unwrap_or_else(|_| "/mecab-dic".to_string());
-    let mut tokenizer = Tokenizer::new(mecab_dic_path.clone());
     //let data = web::Data::new(RwLock::new(tokenizer));
-    let (reload_tx, reload_rx) = postage::broadcast::channel(8);
-    tokenizer
-        .gen_userdic_async(vec![])
-        .await
-        .map_err(anyhow::Error::from)?;
-    tokenizer.reload();
+    let (reload_tx, reload_rx) = postage::broadcast::channel::<String>(8);
+    let (batch_tx, batch_rx) = postage::dispatch::channel::<BatchJob>(1024);
+    // Workers load the current build. Only the first start builds one, with no learned nouns
+    // and no sequence, so the first sync pulls all of them.
+    if versions::Versions::new(&mecab_dic_path)
+        .current()?
+        .is_none()
+    {
+        let path = Path::new(&mecab_dic_path);
+        gen_userdic_async(path, path, vec![], None, None).await?;
+    }
     if let Ok(userdic_server_url) = userdic_server_url {
         let mut reload_tx = reload_tx.clone();
+        let mecab_dic_path = mecab_dic_path.clone();
         actix_web::rt::spawn(async move {
             loop {
                 let res: Result<(), anyhow::Error> = (async {
                     let client = awc::Client::default();
                     loop {
-                        let res = client
-                            .get(&userdic_server_url)
-                            .send()
-                            .await
-                            .map_err(|_| anyhow::Error::msg("userdic server request fail"))?
-                            .body()
-                            .limit(1024 * 1024 * 1024)
-                            .await?
-                            .to_vec();
-                        let nouns: Vec<String> = serde_json::from_slice(&res)?;
-                        if !nouns.is_empty() {
-                            tokenizer.gen_userdic_async(nouns).await?;
-                            tokenizer.reload();
-                            reload_tx.send(()).await?;
+                        if let Some(own_url) = &userdic_subscribe_url {
+                            if let Err(err) = subscribe(&client, &userdic_server_url, own_url).await
+                            {
+                                println!("ERROR: {}", err);
+                            }
+                        }
+                        for tenant in tenant::list(&mecab_dic_path)? {
+                            let url = tenant_nouns_url(&userdic_server_url, &tenant);
+                            let userdic_path = tenant::path(&mecab_dic_path, &tenant);
+                            let res: Result<(), anyhow::Error> = (async {
+                                let mecab_dic_path = Path::new(&mecab_dic_path);
+                                if sync_nouns(&client, mecab_dic_path, &userdic_path, &tenant, &url)
+                                    .await?
+                                {
+                                    reload_tx.send(tenant.clone()).await?;
+                                }
+                                Ok(())
+                            })
+                            .await;
+                            if let Err(err) = res {
+                                println!("ERROR: {}: {}", tenant, err);
+                            }
                         }
                         actix_web::rt::time::sleep(std::time::Duration::from_secs(
                             userdic_sync_interval_seconds,
@@ -150,33 +1260,50 @@ async fn main() -> anyhow::Result<()> {
             }
         });
     }
+    let pending = web::Data::new(PendingSyncs::default());
     Ok(HttpServer::new(move || {
-        let mecab_dic_path = mecab_dic_path.clone();
-        let tokenizer = Tokenizer::new(mecab_dic_path);
-        let tokenizer = web::Data::new(RwLock::new(tokenizer));
+        let tenants = web::Data::new(Tenants::new(&mecab_dic_path));
+        tenants.get(tenant::DEFAULT);
         let mut reload_rx = reload_rx.clone();
-        let tokenizer_ = tokenizer.clone();
+        let tenants_ = tenants.clone();
+        actix_web::rt::spawn(async move {
+            while let Some(tenant) = reload_rx.recv().await {
+                println!("reload tokenizer: {}", tenant);
+                if let Err(err) = tenants_.reload(&tenant).await {
+                    println!("ERROR: {}", err);
+                }
+            }
+        });
+        let mut batch_rx = batch_rx.clone();
+        let tenants_ = tenants.clone();
         actix_web::rt::spawn(async move {
-            while reload_rx.recv().await.is_some() {
-                println!("reload tokenizer");
-                tokenizer_.write().await.reload();
+            while let Some(job) = batch_rx.recv().await {
+                let result = run_batch_job(&tenants_, &job);
+                let mut result_tx = job.result_tx;
+                result_tx.send(result).await.ok();
             }
         });
+        let batch_tx = web::Data::new(batch_tx.clone());
         let reload_tx = web::Data::new(RwLock::new(reload_tx.clone()));
         /*data.read()
             .await
-            .gen_userdic_async(vec![])
+            .gen_userdic_async(vec![], None)
             .await
             .map_err(anyhow::Error::from)?;
         data.write().await.reload();*/
 
         App::new()
-            .app_data(tokenizer)
+            .app_data(tenants)
             .app_data(reload_tx)
-            .service(tokenize)
-            .service(sync_userdic)
-            .service(tokenize_post)
-            .service(userdic)
+            .app_data(batch_tx)
+            .app_data(pending.clone())
+            .app_data(web::PayloadConfig::new(MAX_PAYLOAD_BYTES))
+            .configure(error::extractors)
+            .wrap(error::RequestId)
+            .wrap(metrics::Metrics)
+            .service(export_metrics)
+            .configure(tenant_services)
+            .service(web::scope(tenant::SCOPE).configure(tenant_services))
             .service(Files::new("/", "./static").prefer_utf8(true).index_file("index.html"))
     })
     .bind(&format!("0.0.0.0:{}", port))?
@@ -190,20 +1317,220 @@ mod tests {
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
     use serial_test::serial;
+    use tokenizer::Tokenizer;
 
     fn test_server() -> actix_test::TestServer {
         actix_test::start_with(actix_test::config().h1(), || {
-            let mecab_dic_path = "./mecab-ko-dic".to_string();
-            let tokenizer = Tokenizer::new(mecab_dic_path);
-            let data = web::Data::new(RwLock::new(tokenizer));
+            let tenants = web::Data::new(Tenants::new("./mecab-ko-dic"));
+            let (reload_tx, mut reload_rx) = postage::broadcast::channel::<String>(8);
+            let tenants_ = tenants.clone();
+            actix_web::rt::spawn(async move {
+                while let Some(tenant) = reload_rx.recv().await {
+                    tenants_.reload(&tenant).await.unwrap();
+                }
+            });
+            let (batch_tx, mut batch_rx) = postage::dispatch::channel::<BatchJob>(16);
+            let tenants_ = tenants.clone();
+            actix_web::rt::spawn(async move {
+                while let Some(job) = batch_rx.recv().await {
+                    let result = run_batch_job(&tenants_, &job);
+                    let mut result_tx = job.result_tx;
+                    result_tx.send(result).await.ok();
+                }
+            });
             App::new()
-                .app_data(data)
-                .service(tokenize)
-                .service(sync_userdic)
+                .app_data(tenants)
+                .app_data(web::Data::new(batch_tx))
+                .app_data(web::Data::new(RwLock::new(reload_tx)))
+                .app_data(web::Data::new(PendingSyncs::default()))
+                .configure(error::extractors)
+                .wrap(error::RequestId)
+                .wrap(metrics::Metrics)
+                .service(export_metrics)
+                .configure(tenant_services)
+                .service(web::scope(tenant::SCOPE).configure(tenant_services))
         })
     }
     #[actix_rt::test]
     #[serial]
+    async fn test_tokenize_nbest() {
+        let srv = test_server();
+
+        let mut res = srv
+            .post("/tokenize?n=3&output=surface")
+            .send_body("세종시 ㅋㅋ")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        let candidates = body.as_array().unwrap();
+        assert!(!candidates.is_empty() && candidates.len() <= 3);
+        assert_eq!(
+            candidates[0]["analytics"],
+            serde_json::json!(["세종시", "ㅋㅋ"])
+        );
+        assert!(candidates[0]["cost"].is_i64());
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_lemmatize() {
+        let srv = test_server();
+
+        let mut res = srv
+            .get("/lemmatize?q=%EA%B0%94%EB%8B%A4&include=V*")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body, serde_json::json!(["가다"]));
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tokenize_normalize_options() {
+        let srv = test_server();
+
+        let mut res = srv
+            .get("/tokenize?q=%E3%85%8B%E3%85%8B%E3%85%8B%E3%85%8B%E3%85%8B")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["token"], "ㅋㅋㅋ");
+
+        let mut res = srv
+            .get("/tokenize?q=%E3%85%8B%E3%85%8B%E3%85%8B%E3%85%8B%E3%85%8B&derepeat=0")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["token"], "ㅋㅋㅋㅋㅋ");
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tokenize_projection() {
+        let srv = test_server();
+
+        let mut res = srv
+            .post("/tokenize?include=NN*&expand=true&output=surface")
+            .send_body("세종시 ㅋㅋ")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body, serde_json::json!(["세종", "시"]));
+
+        let mut res = srv
+            .post("/tokenize?exclude=UNKNOWN&output=pairs")
+            .send_body("세종시 ㅋㅋ")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{ "token": "세종시", "tag": "NNP" }])
+        );
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tokenize_stream() {
+        let srv = test_server();
+
+        let mut res = srv
+            .post("/tokenize/stream")
+            .content_type("application/x-ndjson")
+            .send_body("안녕\n\n세종시 ㅋㅋ")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body = String::from_utf8(res.body().await.unwrap().to_vec()).unwrap();
+        let records = body
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0][0]["token"], "안녕");
+        assert_eq!(records[1].as_array().unwrap().len(), 0);
+        assert_eq!(records[2][0]["token"], "세종시");
+
+        let mut body = "안녕\n".as_bytes().to_vec();
+        body.extend_from_slice(b"\xff\xfe\n");
+        body.extend_from_slice("세종시\n".as_bytes());
+        let mut res = srv
+            .post("/tokenize/stream")
+            .content_type("application/x-ndjson")
+            .send_body(body)
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body = String::from_utf8(res.body().await.unwrap().to_vec()).unwrap();
+        let records = body
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0][0]["token"], "안녕");
+        assert!(records[1]["error"].as_str().unwrap().contains("UTF-8"));
+        assert_eq!(records[2][0]["token"], "세종시");
+    }
+    #[test]
+    fn test_line_splitter() {
+        let mut splitter = LineSplitter::new(8);
+        let mut lines = VecDeque::new();
+        for chunk in [&b"ab"[..], b"c\r\nd\xff\n", b"0123456789", b"x\n\nlast"] {
+            splitter.push(chunk, &mut lines);
+        }
+        lines.extend(splitter.finish());
+        let lines = lines
+            .into_iter()
+            .map(|line| line.map_err(|err| err.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0].as_deref(), Ok("abc"));
+        assert!(lines[1].as_ref().unwrap_err().contains("UTF-8"));
+        assert!(lines[2]
+            .as_ref()
+            .unwrap_err()
+            .contains("longer than 8 bytes"));
+        assert_eq!(lines[3].as_deref(), Ok(""));
+        assert_eq!(lines[4].as_deref(), Ok("last"));
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tokenize_batch() {
+        let srv = test_server();
+
+        let mut res = srv
+            .post("/tokenize/batch")
+            .send_json(&serde_json::json!([
+                { "id": "a", "text": "안녕" },
+                { "id": "b", "text": "세종시 ㅋㅋ" },
+            ]))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["id"], "a");
+        assert_eq!(body[0]["analytics"][0]["token"], "안녕");
+        assert_eq!(body[1]["id"], "b");
+        assert_eq!(body[1]["analytics"].as_array().unwrap().len(), 2);
+
+        let mut res = srv
+            .post("/tokenize/batch")
+            .content_type("application/x-ndjson")
+            .send_body("{\"id\":\"a\",\"text\":\"안녕\"}\n{\"id\":\"b\",\"text\":\"안녕\"}\n")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[1]["id"], "b");
+    }
+    #[actix_rt::test]
+    #[serial]
     async fn test_example() {
         let srv = test_server();
 
@@ -222,14 +1549,453 @@ mod tests {
         let mecab_dic_path = "./mecab-ko-dic".to_string();
         let mut tokenizer = Tokenizer::new(mecab_dic_path);
         tokenizer
-            .gen_userdic_async(vec!["뤣쉙퀡".to_string()])
+            .gen_userdic_async(vec!["뤣쉙퀡".to_string()], None, None)
             .await
             .unwrap();
         tokenizer.reload();
         let res = tokenizer.tokenize("뤣쉙퀡").unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(res[0].tags.len(), 1);
-        assert_eq!(res[0].tags[0], "NNG");
+        assert_eq!(res[0].tags[0], Tag::NNG);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_named_userdics() {
+        let srv = test_server();
+
+        let res = srv
+            .put("/userdics/slang")
+            .send_json(&serde_json::json!({ "pos": "NNG", "priority": 1, "words": ["뤣쉙퀡"] }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 201);
+        let mut res = srv.get("/userdics").send().await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["name"], "slang");
+        assert_eq!(body[0]["pos"], "NNG");
+
+        let mut res = srv
+            .post("/tokenize?output=pairs")
+            .send_body("뤣쉙퀡")
+            .await
+            .unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{ "token": "뤣쉙퀡", "tag": "NNG" }])
+        );
+
+        let res = srv.delete("/userdics/slang").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv.get("/userdics/slang").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        let res = srv
+            .put("/userdics/rest-mecab")
+            .send_json(&serde_json::json!({ "words": [] }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let mut res = srv
+            .put("/userdics/slang")
+            .send_json(&serde_json::json!({ "words": ["뤣쉙퀡", "a,b", "c\nd"] }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert!(body.message.contains("a,b") && body.message.contains("c\\nd"));
+        let res = srv.get("/userdics/slang").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        let mut res = srv
+            .put("/userdics/slang")
+            .send_json(&serde_json::json!({ "pos": "NNQ", "words": ["뤣쉙퀡"] }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.message, "unknown POS: NNQ");
+        let res = srv.get("/userdics/slang").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_userdic_entries() {
+        let srv = test_server();
+
+        let res = srv
+            .put("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send_json(
+                &serde_json::json!({ "pos": "NNG", "semantic_group": "인명", "cost": -1000 }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 201);
+        let mut res = srv.get("/userdic/entries").send().await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["surface"], "뤣쉙퀡");
+        assert_eq!(body[0]["cost"], -1000);
+
+        let mut res = srv.post("/tokenize").send_body("뤣쉙퀡").await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["token"], "뤣쉙퀡");
+        assert_eq!(body[0]["tags"], serde_json::json!(["NNG"]));
+        assert_eq!(body[0]["symantic_group"], "인명");
+
+        let mut res = srv
+            .put("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send_json(&serde_json::json!({ "pos": "NNQ" }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.message, "unknown POS: NNQ");
+        let mut res = srv
+            .get("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        let entry: Entry = res.json().await.unwrap();
+        assert_eq!(entry.pos, Tag::NNG);
+
+        let res = srv
+            .delete("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv
+            .get("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_userdic_versions() {
+        let srv = test_server();
+
+        let res = srv
+            .put("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send_json(&serde_json::json!({ "pos": "NNG" }))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = srv
+            .delete("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+
+        let mut res = srv.get("/userdic/versions").send().await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        let versions = body["versions"].as_array().unwrap();
+        let (with, without) = (
+            versions[versions.len() - 2]["version"].as_u64().unwrap(),
+            versions[versions.len() - 1]["version"].as_u64().unwrap(),
+        );
+        assert_eq!(body["current"], without);
+
+        let mut res = srv
+            .get(format!("/userdic/versions/{}/diff/{}", with, without))
+            .send()
+            .await
+            .unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body["removed"], serde_json::json!(["뤣쉙퀡"]));
+        assert_eq!(body["added"], serde_json::json!([]));
+
+        let res = srv
+            .post(format!("/userdic/versions/{}/rollback", with))
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let mut res = srv.post("/tokenize").send_body("뤣쉙퀡").await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["tags"], serde_json::json!(["NNG"]));
+        let res = srv
+            .get("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+
+        let res = srv
+            .post(format!("/userdic/versions/{}/rollback", without + 1000))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        let res = srv
+            .post(format!("/userdic/versions/{}/rollback", without))
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = srv
+            .get("/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_notify_userdic() {
+        let srv = test_server();
+        let dir = tenant::path("./mecab-ko-dic", "notify-test");
+        let builds = versions::Versions::new(&dir);
+        let (version, _) = builds.create().unwrap();
+        builds
+            .write_meta(&Meta {
+                version,
+                created_at: versions::now(),
+                entries: 0,
+                source: None,
+                hash: None,
+                learned_seq: Some(5),
+            })
+            .unwrap();
+        builds.activate(version).unwrap();
+
+        let res = srv
+            .post("/t/notify-test/userdic/notify")
+            .send_json(&serde_json::json!({ "seq": 5 }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv
+            .post("/t/notify-nobody/userdic/notify")
+            .send_json(&serde_json::json!({ "seq": 5 }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        assert!(!tenant::path("./mecab-ko-dic", "notify-nobody").exists());
+
+        async fn changes_page() -> HttpResponse {
+            let change = |seq, noun: &str| changes::Change {
+                seq,
+                op: changes::Op::Add,
+                noun: noun.to_string(),
+            };
+            HttpResponse::Ok().json(changes::Page {
+                first: 1,
+                last: 7,
+                changes: vec![change(6, "뤣쉙퀡"), change(7, "톩쒧")],
+                more: false,
+            })
+        }
+        let upstream = actix_test::start(|| {
+            App::new().route("/t/notify-test/nouns/changes", web::get().to(changes_page))
+        });
+        std::env::set_var("USERDIC_SERVER_URL", upstream.url("/nouns"));
+        std::env::set_var("USERDIC_NOTIFY_JITTER_SECONDS", "0");
+        let res = srv
+            .post("/t/notify-test/userdic/notify")
+            .send_json(&serde_json::json!({ "seq": 7 }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 202);
+        let mut status = serde_json::Value::Null;
+        for _ in 0..100 {
+            let mut res = srv
+                .get("/t/notify-test/userdic/status")
+                .send()
+                .await
+                .unwrap();
+            status = res.json().await.unwrap();
+            if status["learned_seq"] == 7 {
+                break;
+            }
+            actix_rt::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        std::env::remove_var("USERDIC_SERVER_URL");
+        std::env::remove_var("USERDIC_NOTIFY_JITTER_SECONDS");
+        assert_eq!(status["learned_seq"], 7);
+        assert_eq!(status["last_sync"]["ok"], true);
+        assert_eq!(status["last_sync"]["changed"], true);
+        let learned = UserDics::new(&dir).learned_words().unwrap();
+        assert_eq!(learned, vec!["뤣쉙퀡", "톩쒧"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_userdic_status() {
+        let srv = test_server();
+
+        let res = srv
+            .put("/t/status-test/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send_json(&serde_json::json!({ "pos": "NNG", "semantic_group": "상품" }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 201);
+        let mut res = srv
+            .get("/t/status-test/userdic/status")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let status: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(status["tenant"], "status-test");
+        assert_eq!(status["entries"], 1);
+        assert_eq!(status["hash"].as_str().unwrap().len(), 40);
+        assert!(status["version"].as_u64().is_some());
+        assert!(status["last_sync"].is_null());
+
+        let mut res = srv.get("/t/status-test/userdic").send().await.unwrap();
+        let entries: Vec<Entry> = res.json().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].surface, "뤣쉙퀡");
+        assert_eq!(entries[0].semantic_group.as_deref(), Some("상품"));
+        let mut res = srv
+            .get("/t/status-test/userdic?format=csv")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(res.body().await.unwrap().to_vec()).unwrap(),
+            "뤣쉙퀡,,,,NNG,상품,T,뤣쉙퀡,*,*,*,*\n"
+        );
+        let res = srv
+            .get("/t/status-test/userdic?format=xml")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        std::fs::remove_dir_all(tenant::path("./mecab-ko-dic", "status-test")).unwrap();
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_errors() {
+        let srv = test_server();
+
+        let mut res = srv
+            .post("/tokenize")
+            .insert_header((error::REQUEST_ID_HEADER, "req-1"))
+            .send_body(vec![0xff, 0xfe])
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "bad_request");
+        assert_eq!(body.request_id.as_deref(), Some("req-1"));
+
+        let mut res = srv.get("/userdics/missing").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        assert!(res.headers().get(error::REQUEST_ID_HEADER).is_some());
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "not_found");
+        let mut res = srv.get("/tokenize?n=x&q=a").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "bad_request");
+
+        // Nothing listens there, so the sync fails upstream.
+        std::env::set_var("USERDIC_SERVER_URL", "http://127.0.0.1:1/nouns");
+        let res = srv.post("/t/sync-errors-test/sync-userdic").send().await;
+        std::env::remove_var("USERDIC_SERVER_URL");
+        let mut res = res.unwrap();
+        assert_eq!(res.status().as_u16(), 502);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "upstream_failed");
+        std::fs::remove_dir_all(tenant::path("./mecab-ko-dic", "sync-errors-test")).unwrap();
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_metrics() {
+        let srv = test_server();
+
+        srv.get("/tokenize?q=%EC%95%88%EB%85%95")
+            .send()
+            .await
+            .unwrap();
+        srv.post("/tokenize").send_body("안녕").await.unwrap();
+        srv.post("/t/metrics-test/tokenize")
+            .send_body("안녕")
+            .await
+            .unwrap();
+        let mut res = srv.get("/metrics").send().await.unwrap();
+        assert!(res.status().is_success());
+        let body = String::from_utf8(res.body().await.unwrap().to_vec()).unwrap();
+        for series in &[
+            r#"http_requests_total{method="GET",route="/tokenize",status="200"}"#,
+            r#"http_requests_total{method="POST",route="/tokenize",status="200"}"#,
+            r#"http_requests_total{method="POST",route="/t/{tenant}/tokenize",status="404"}"#,
+            r#"http_request_duration_seconds_bucket{method="GET",route="/tokenize""#,
+            r#"tokenizer_userdic_entries{tenant="default"}"#,
+            "tokenizer_tokenized_bytes_total ",
+            "tokenizer_tokens_total ",
+        ] {
+            assert!(body.contains(series), "{}", series);
+        }
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tenants() {
+        let srv = test_server();
+
+        // Reads don't create a tenant.
+        let res = srv
+            .post("/t/unknown-tenant/tokenize")
+            .send_body("뤣쉙퀡")
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        let res = srv
+            .get("/t/unknown-tenant/userdic/entries")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        assert!(!tenant::path("./mecab-ko-dic", "unknown-tenant").exists());
+
+        let res = srv
+            .put("/t/acme/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send_json(&serde_json::json!({ "pos": "NNG", "semantic_group": "상품" }))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 201);
+        let mut res = srv
+            .post("/t/acme/tokenize")
+            .send_body("뤣쉙퀡")
+            .await
+            .unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["token"], "뤣쉙퀡");
+        assert_eq!(body[0]["symantic_group"], "상품");
+        let mut res = srv
+            .post("/t/acme/tokenize/batch")
+            .send_json(&serde_json::json!([{ "id": "1", "text": "뤣쉙퀡" }]))
+            .await
+            .unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body[0]["analytics"][0]["symantic_group"], "상품");
+
+        // Other tenants keep their own dictionaries.
+        let mut res = srv.get("/userdic/entries").send().await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert!(!body
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|entry| entry["surface"] == "뤣쉙퀡"));
+        let mut res = srv.post("/tokenize").send_body("뤣쉙퀡").await.unwrap();
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_ne!(body[0]["symantic_group"], "상품");
+
+        let res = srv
+            .delete("/t/acme/userdic/entries/%EB%A4%A3%EC%89%99%ED%80%A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv
+            .post("/t/a.b/tokenize")
+            .send_body("뤣쉙퀡")
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
     }
     #[actix_rt::test]
     #[serial]
@@ -1,25 +1,27 @@
-use actix_web::{get, post, web, App, FromRequest, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    delete, get, post, put, web, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 
 use noun_extractor::model::{Score, State as NounExtractorState};
 use serde::Deserialize;
 
 use async_rwlock::RwLock;
-use rocksdb::{BlockBasedOptions, IteratorMode, Options, DB};
+use error::Error;
+use rocksdb::{BlockBasedOptions, ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
 
-use std::path::Path;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-use hangul_normalize::{control_chars, derepeat, whitespace_less};
+pub mod changes;
+pub mod error;
+pub mod metrics;
+pub mod tenant;
 
-#[derive(Debug, derive_more::Display, derive_more::Error)]
-struct Error {
-    err: anyhow::Error,
-}
-impl actix_web::error::ResponseError for Error {}
-impl From<anyhow::Error> for Error {
-    fn from(err: anyhow::Error) -> Error {
-        Error { err }
-    }
-}
+use changes::{Change, Notice, Op};
+
+use hangul_normalize::NormalizeConfig;
 
 fn rocksdb_default_opts() -> Options {
     let mut opts = Options::default();
@@ -44,44 +46,339 @@ fn rocksdb_default_opts() -> Options {
     opts
 }
 
-struct State {
-    noun_scores: DB,
-    noun_extractor: NounExtractorState,
-    unique_suffixes_count_threshold: f64,
-    count_threshold: u32,
-    noun_probability_threshold: f32,
-    nouns: DB,
+/// Keyspaces of a tenant, as column families of its database.
+const NOUN_SCORES: &str = "noun_scores";
+const NOUNS: &str = "nouns";
+/// Words that never make it into `nouns`, whatever their score.
+const BLOCKED: &str = "blocked";
+/// Every addition to and removal from `nouns`, keyed by big-endian sequence number.
+const CHANGES: &str = "changes";
+/// Changes a tenant's log keeps by default. Older ones are dropped as new ones are logged, and
+/// a tokenizer that synced before them pulls every noun instead.
+const CHANGES_KEPT: u64 = 1_000_000;
+/// Nouns logged per batch when a log is rebuilt.
+const LOG_BATCH: usize = 10000;
+/// URLs of the tokenizers to notify of changes, as a JSON array under the store path.
+const SUBSCRIBERS: &str = "subscribers.json";
+/// Notices in a row a subscriber may fail to take before it is dropped.
+const NOTIFY_FAILURES: u32 = 3;
+
+type KeyValue = (Box<[u8]>, Box<[u8]>);
+
+/// One of a tenant's keyspaces. The default tenant predates tenants and keeps a database per
+/// keyspace, but for the change log, which is a column family of the nouns' database. The
+/// others keep one database with a column family per keyspace.
+struct Keyspace {
+    db: Arc<DB>,
+    cf: Option<&'static str>,
 }
-impl State {
-    fn open<P: AsRef<Path>>(noun_extractor_model_path: P, store_path: P) -> anyhow::Result<Self> {
-        Ok(Self {
-            noun_extractor: NounExtractorState::open(noun_extractor_model_path)?,
-            noun_scores: DB::open(
-                &rocksdb_default_opts(),
-                store_path.as_ref().join("noun_scores"),
-            )?,
-            nouns: DB::open(&rocksdb_default_opts(), store_path.as_ref().join("nouns"))?,
-            unique_suffixes_count_threshold: 5.0,
-            count_threshold: 30,
-            noun_probability_threshold: 0.9,
+impl Keyspace {
+    fn cf(&self) -> anyhow::Result<Option<&ColumnFamily>> {
+        match self.cf {
+            Some(name) => Ok(Some(self.db.cf_handle(name).ok_or_else(|| {
+                anyhow::Error::msg(format!("missing column family {}", name))
+            })?)),
+            None => Ok(None),
+        }
+    }
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(match self.cf()? {
+            Some(cf) => self.db.get_cf(cf, key)?,
+            None => self.db.get(key)?,
+        })
+    }
+    fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => self.db.put_cf(cf, key, value)?,
+            None => self.db.put(key, value)?,
+        }
+        Ok(())
+    }
+    fn delete<K: AsRef<[u8]>>(&self, key: K) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => self.db.delete_cf(cf, key)?,
+            None => self.db.delete(key)?,
+        }
+        Ok(())
+    }
+    /// Batches are written with [`Keyspace::write`] on a keyspace of the same database.
+    fn put_in<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        batch: &mut WriteBatch,
+        key: K,
+        value: V,
+    ) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => batch.put_cf(cf, key, value),
+            None => batch.put(key, value),
+        }
+        Ok(())
+    }
+    fn delete_in<K: AsRef<[u8]>>(&self, batch: &mut WriteBatch, key: K) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => batch.delete_cf(cf, key),
+            None => batch.delete(key),
+        }
+        Ok(())
+    }
+    /// Deletes the keys from `from` up to but not including `to`.
+    fn delete_range_in<K: AsRef<[u8]>>(
+        &self,
+        batch: &mut WriteBatch,
+        from: K,
+        to: K,
+    ) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => batch.delete_range_cf(cf, from, to),
+            None => batch.delete_range(from, to),
+        }
+        Ok(())
+    }
+    fn write(&self, batch: WriteBatch) -> anyhow::Result<()> {
+        Ok(self.db.write(batch)?)
+    }
+    fn set_options(&self, opts: &[(&str, &str)]) -> anyhow::Result<()> {
+        match self.cf()? {
+            Some(cf) => self.db.set_options_cf(cf, opts)?,
+            None => self.db.set_options(opts)?,
+        }
+        Ok(())
+    }
+    fn entries(&self, mode: IteratorMode, limit: usize) -> anyhow::Result<Vec<KeyValue>> {
+        Ok(match self.cf()? {
+            Some(cf) => self.db.iterator_cf(cf, mode).take(limit).collect(),
+            None => self.db.iterator(mode).take(limit).collect(),
+        })
+    }
+    /// An integer RocksDB property of the keyspace, 0 when RocksDB does not have it.
+    fn property(&self, name: &str) -> anyhow::Result<u64> {
+        Ok(match self.cf()? {
+            Some(cf) => self.db.property_int_value_cf(cf, name)?,
+            None => self.db.property_int_value(name)?,
+        }
+        .unwrap_or(0))
+    }
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .entries(IteratorMode::Start, usize::MAX)?
+            .into_iter()
+            .map(|(k, _)| String::from_utf8_lossy(&k).to_string())
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+    unique_suffixes_count: f64,
+    count: u32,
+    noun_probability: f32,
+}
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            unique_suffixes_count: 5.0,
+            count: 30,
+            noun_probability: 0.9,
+        }
+    }
+}
+
+/// A tenant's noun scores, learned nouns, blocklist and change log, and the thresholds it
+/// learns with.
+struct Tenant {
+    noun_scores: Keyspace,
+    nouns: Keyspace,
+    blocked: Keyspace,
+    changes: Keyspace,
+    /// Sequence of the last logged change.
+    seq: AtomicU64,
+    /// Sequence subscribers were last notified of.
+    notified: u64,
+    thresholds: Thresholds,
+    /// Changes the log keeps.
+    changes_kept: u64,
+}
+impl Tenant {
+    fn open_default<P: AsRef<Path>>(
+        store_path: P,
+        thresholds: Thresholds,
+        changes_kept: u64,
+    ) -> anyhow::Result<Self> {
+        let open = |name: &str| -> anyhow::Result<Keyspace> {
+            Ok(Keyspace {
+                db: Arc::new(DB::open(
+                    &rocksdb_default_opts(),
+                    store_path.as_ref().join(name),
+                )?),
+                cf: None,
+            })
+        };
+        let mut opts = rocksdb_default_opts();
+        opts.create_missing_column_families(true);
+        let nouns_db = Arc::new(DB::open_cf(
+            &opts,
+            store_path.as_ref().join(NOUNS),
+            [CHANGES],
+        )?);
+        let tenant = Self {
+            noun_scores: open(NOUN_SCORES)?,
+            nouns: Keyspace {
+                db: nouns_db.clone(),
+                cf: None,
+            },
+            blocked: open(BLOCKED)?,
+            changes: Keyspace {
+                db: nouns_db,
+                cf: Some(CHANGES),
+            },
+            seq: AtomicU64::new(0),
+            notified: 0,
+            thresholds,
+            changes_kept,
+        };
+        // The log used to be a database of its own. It is rebuilt from the nouns, numbered on
+        // from where the old one ended so tokenizers don't take the new one for a step back.
+        let old_changes = store_path.as_ref().join(CHANGES);
+        if old_changes.exists() && tenant.changes.entries(IteratorMode::Start, 1)?.is_empty() {
+            let old_last = open(CHANGES)?.entries(IteratorMode::End, 1)?;
+            if let Some((key, _)) = old_last.first() {
+                tenant.seq.store(seq_of(key), Ordering::SeqCst);
+            }
+        }
+        let tenant = tenant.start_changes()?;
+        if old_changes.exists() {
+            std::fs::remove_dir_all(old_changes)?;
+        }
+        Ok(tenant)
+    }
+    fn open<P: AsRef<Path>>(
+        path: P,
+        thresholds: Thresholds,
+        changes_kept: u64,
+    ) -> anyhow::Result<Self> {
+        let mut opts = rocksdb_default_opts();
+        opts.create_missing_column_families(true);
+        let db = Arc::new(DB::open_cf(
+            &opts,
+            path,
+            [NOUN_SCORES, NOUNS, BLOCKED, CHANGES],
+        )?);
+        let keyspace = |cf: &'static str| Keyspace {
+            db: db.clone(),
+            cf: Some(cf),
+        };
+        Self {
+            noun_scores: keyspace(NOUN_SCORES),
+            nouns: keyspace(NOUNS),
+            blocked: keyspace(BLOCKED),
+            changes: keyspace(CHANGES),
+            seq: AtomicU64::new(0),
+            notified: 0,
+            thresholds,
+            changes_kept,
+        }
+        .start_changes()
+    }
+    /// Picks up the change log where it ended and drops what it keeps no more. Nouns learned
+    /// before there was a log are logged as additions, so replaying the log from the start
+    /// gives every noun.
+    fn start_changes(mut self) -> anyhow::Result<Self> {
+        let last = self.changes.entries(IteratorMode::End, 1)?;
+        match last.first() {
+            Some((key, _)) => {
+                self.seq.store(seq_of(key), Ordering::SeqCst);
+                self.notified = seq_of(key);
+            }
+            None => {
+                let mut seq = self.seq.load(Ordering::SeqCst);
+                for chunk in self.nouns()?.chunks(LOG_BATCH) {
+                    let mut batch = WriteBatch::default();
+                    for noun in chunk {
+                        seq += 1;
+                        self.log(&mut batch, seq, Op::Add, noun)?;
+                    }
+                    self.changes.write(batch)?;
+                    self.seq.store(seq, Ordering::SeqCst);
+                }
+            }
+        }
+        let seq = self.seq.load(Ordering::SeqCst);
+        if seq > self.changes_kept {
+            let mut batch = WriteBatch::default();
+            self.changes.delete_range_in(
+                &mut batch,
+                0u64.to_be_bytes(),
+                (seq - self.changes_kept + 1).to_be_bytes(),
+            )?;
+            self.changes.write(batch)?;
+        }
+        Ok(self)
+    }
+    /// Logs a change in the batch, dropping the oldest one the log keeps no more.
+    fn log(&self, batch: &mut WriteBatch, seq: u64, op: Op, noun: &str) -> anyhow::Result<()> {
+        let noun = noun.to_string();
+        self.changes.put_in(
+            batch,
+            seq.to_be_bytes(),
+            bincode::serialize(&Change { seq, op, noun })?,
+        )?;
+        if seq > self.changes_kept {
+            self.changes
+                .delete_in(batch, (seq - self.changes_kept).to_be_bytes())?;
+        }
+        Ok(())
+    }
+    /// Adds or removes a noun and logs it in one write. Callers hold the state's write lock,
+    /// so changes are logged in sequence order.
+    fn change_noun(&self, op: Op, noun: &str) -> anyhow::Result<()> {
+        let seq = self.seq.load(Ordering::SeqCst) + 1;
+        let mut batch = WriteBatch::default();
+        self.log(&mut batch, seq, op, noun)?;
+        match op {
+            Op::Add => self.nouns.put_in(&mut batch, noun, &[0])?,
+            Op::Remove => self.nouns.delete_in(&mut batch, noun)?,
+        }
+        self.nouns.write(batch)?;
+        self.seq.store(seq, Ordering::SeqCst);
+        Ok(())
+    }
+    fn add_noun(&self, noun: &str) -> anyhow::Result<()> {
+        if self.nouns.get(noun)?.is_none() {
+            self.change_noun(Op::Add, noun)?;
+        }
+        Ok(())
+    }
+    fn remove_noun(&self, noun: &str) -> anyhow::Result<()> {
+        if self.nouns.get(noun)?.is_some() {
+            self.change_noun(Op::Remove, noun)?;
+        }
+        Ok(())
+    }
+    /// Up to `limit` changes after `since`, oldest first, and the oldest change still logged.
+    fn changes(&self, since: u64, limit: usize) -> anyhow::Result<changes::Page> {
+        let last = self.seq.load(Ordering::SeqCst);
+        let first = match self.changes.entries(IteratorMode::Start, 1)?.first() {
+            Some((key, _)) => seq_of(key),
+            None => last + 1,
+        };
+        let from = since.saturating_add(1).to_be_bytes();
+        let changes = self
+            .changes
+            .entries(IteratorMode::From(&from, Direction::Forward), limit)?
+            .into_iter()
+            .map(|(_, change)| bincode::deserialize::<Change>(&change))
+            .collect::<Result<Vec<_>, _>>()?;
+        let more = matches!(changes.last(), Some(change) if change.seq < last);
+        Ok(changes::Page {
+            first,
+            last,
+            changes,
+            more,
         })
     }
-    pub fn set_threshold(
-        &mut self,
-        unique_suffixes_count: f64,
-        count: u32,
-        noun_probability: f32,
-    ) -> &mut Self {
-        self.unique_suffixes_count_threshold = unique_suffixes_count;
-        self.count_threshold = count;
-        self.noun_probability_threshold = noun_probability;
-        self
-    }
-    fn train(&mut self, s: String) -> anyhow::Result<i32> {
-        let s = control_chars(&s, "_");
-        let s = whitespace_less(&s);
-        let s = derepeat(&s, 3);
-        let mut scores = self.noun_extractor.extract_nouns(&s)?;
+    fn train(&self, noun_extractor: &NounExtractorState, s: String) -> anyhow::Result<i32> {
+        let s = NormalizeConfig::default().apply(&s);
+        let mut scores = noun_extractor.extract_nouns(&s)?;
         for (candidate, score) in scores.iter_mut() {
             let key = bincode::serialize(&candidate)?;
             if let Some(prev_score) = self.noun_scores.get(&key)? {
@@ -101,14 +398,11 @@ impl State {
         for (candidate, score) in scores {
             let key = bincode::serialize(&candidate)?;
             self.noun_scores.put(key, bincode::serialize(&score)?)?;
-            if score.noun_probability >= self.noun_probability_threshold
-                && score.unique_suffixes_hll.len() >= self.unique_suffixes_count_threshold
-                && score.count >= self.count_threshold
-            {
-                self.nouns.put(candidate, &[0])?;
+            if self.passes(&score) && !self.is_blocked(&candidate)? {
+                self.add_noun(&candidate)?;
                 count += 1;
             } else {
-                self.nouns.delete(&candidate)?;
+                self.remove_noun(&candidate)?;
             }
         }
         self.nouns
@@ -117,6 +411,45 @@ impl State {
             .set_options(&[("disable_auto_compactions", "false")])?;
         Ok(count)
     }
+    /// Keyspaces by column family name.
+    fn keyspaces(&self) -> [(&'static str, &Keyspace); 4] {
+        [
+            (NOUN_SCORES, &self.noun_scores),
+            (NOUNS, &self.nouns),
+            (BLOCKED, &self.blocked),
+            (CHANGES, &self.changes),
+        ]
+    }
+    fn passes(&self, score: &Score) -> bool {
+        score.noun_probability >= self.thresholds.noun_probability
+            && score.unique_suffixes_hll.len() >= self.thresholds.unique_suffixes_count
+            && score.count >= self.thresholds.count
+    }
+    fn is_blocked(&self, word: &str) -> anyhow::Result<bool> {
+        Ok(self.blocked.get(word)?.is_some())
+    }
+    /// Blocks a word and drops it from the learned nouns.
+    fn block(&self, word: &str) -> anyhow::Result<()> {
+        self.blocked.put(word, &[0])?;
+        self.remove_noun(word)
+    }
+    /// Unblocks a word, putting it back into the learned nouns if its score still passes.
+    /// Returns false when the word was not blocked.
+    fn unblock(&self, word: &str) -> anyhow::Result<bool> {
+        if !self.is_blocked(word)? {
+            return Ok(false);
+        }
+        self.blocked.delete(word)?;
+        if let Some(score) = self.noun_score(word)? {
+            if self.passes(&score) {
+                self.add_noun(word)?;
+            }
+        }
+        Ok(true)
+    }
+    fn blocked(&self) -> anyhow::Result<Vec<String>> {
+        self.blocked.keys()
+    }
     fn noun_score(&self, noun: &str) -> anyhow::Result<Option<Score>> {
         let key = bincode::serialize(&noun)?;
         if let Some(bytes) = self.noun_scores.get(&key)? {
@@ -125,23 +458,267 @@ impl State {
             Ok(None)
         }
     }
-    fn nouns(&self) -> Vec<String> {
-        self.nouns
-            .iterator(IteratorMode::Start)
-            .map(|(k, _)| String::from_utf8_lossy(&k).to_string())
-            .collect()
+    fn nouns(&self) -> anyhow::Result<Vec<String>> {
+        let mut learned = self.nouns.keys()?;
+        learned.retain(|noun| !matches!(self.is_blocked(noun), Ok(true)));
+        Ok(learned)
+    }
+}
+
+/// The sequence number a change is logged under.
+fn seq_of(key: &[u8]) -> u64 {
+    let mut seq = [0; 8];
+    seq.copy_from_slice(key);
+    u64::from_be_bytes(seq)
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+struct State {
+    noun_extractor: NounExtractorState,
+    store_path: PathBuf,
+    /// Thresholds a tenant starts with.
+    thresholds: Thresholds,
+    tenants: HashMap<String, Tenant>,
+    subscribers: BTreeSet<String>,
+    /// Notices each subscriber failed to take since it last took one.
+    failures: HashMap<String, u32>,
+    /// Changes a tenant's log grows by before subscribers are notified.
+    notify_delta: u64,
+    /// Changes a tenant's log keeps.
+    changes_kept: u64,
+}
+impl State {
+    fn open<P: AsRef<Path>>(
+        noun_extractor_model_path: P,
+        store_path: P,
+        thresholds: Thresholds,
+        notify_delta: u64,
+        changes_kept: u64,
+    ) -> anyhow::Result<Self> {
+        let subscribers_path = store_path.as_ref().join(SUBSCRIBERS);
+        let subscribed = if subscribers_path.exists() {
+            serde_json::from_slice(&std::fs::read(subscribers_path)?)?
+        } else {
+            BTreeSet::new()
+        };
+        let mut state = Self {
+            noun_extractor: NounExtractorState::open(noun_extractor_model_path)?,
+            store_path: store_path.as_ref().to_path_buf(),
+            thresholds,
+            tenants: HashMap::new(),
+            subscribers: subscribed,
+            failures: HashMap::new(),
+            notify_delta: notify_delta.max(1),
+            changes_kept: changes_kept.max(1),
+        };
+        for name in tenant::list(&state.store_path)? {
+            state.open_tenant(&name)?;
+        }
+        Ok(state)
+    }
+    /// Opens a tenant, creating its database on first use.
+    fn open_tenant(&mut self, name: &str) -> anyhow::Result<&Tenant> {
+        if !self.tenants.contains_key(name) {
+            let tenant = if name == tenant::DEFAULT {
+                Tenant::open_default(&self.store_path, self.thresholds, self.changes_kept)?
+            } else {
+                Tenant::open(
+                    tenant::path(&self.store_path, name),
+                    self.thresholds,
+                    self.changes_kept,
+                )?
+            };
+            self.tenants.insert(name.to_string(), tenant);
+        }
+        Ok(&self.tenants[name])
+    }
+    /// A tenant that has any data.
+    fn tenant(&self, name: &str) -> Option<&Tenant> {
+        self.tenants.get(name)
+    }
+    /// Thresholds are kept in memory, a restart brings every tenant back to the defaults.
+    pub fn set_threshold(&mut self, tenant: &str, thresholds: Thresholds) -> anyhow::Result<()> {
+        self.open_tenant(tenant)?;
+        if let Some(tenant) = self.tenants.get_mut(tenant) {
+            tenant.thresholds = thresholds;
+        }
+        Ok(())
+    }
+    fn train(&mut self, tenant: &str, s: String) -> anyhow::Result<i32> {
+        self.open_tenant(tenant)?;
+        self.tenants[tenant].train(&self.noun_extractor, s)
+    }
+    fn write_subscribers(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.store_path)?;
+        std::fs::write(
+            self.store_path.join(SUBSCRIBERS),
+            serde_json::to_vec_pretty(&self.subscribers)?,
+        )?;
+        Ok(())
+    }
+    /// Returns false when the URL was subscribed already.
+    fn subscribe(&mut self, url: &str) -> anyhow::Result<bool> {
+        if !is_http_url(url) {
+            return Err(anyhow::Error::msg(format!("not an http URL: {}", url)));
+        }
+        if !self
+            .subscribers
+            .insert(url.trim_end_matches('/').to_string())
+        {
+            return Ok(false);
+        }
+        self.write_subscribers()?;
+        Ok(true)
+    }
+    /// Returns false when the URL was not subscribed.
+    fn unsubscribe(&mut self, url: &str) -> anyhow::Result<bool> {
+        self.failures.remove(url.trim_end_matches('/'));
+        if !self.subscribers.remove(url.trim_end_matches('/')) {
+            return Ok(false);
+        }
+        self.write_subscribers()?;
+        Ok(true)
+    }
+    fn notice_taken(&mut self, url: &str) {
+        self.failures.remove(url);
+    }
+    /// Drops the subscriber once it failed [`NOTIFY_FAILURES`] notices in a row.
+    fn notice_failed(&mut self, url: &str) -> anyhow::Result<()> {
+        let failures = self.failures.entry(url.to_string()).or_insert(0);
+        *failures += 1;
+        if *failures >= NOTIFY_FAILURES {
+            self.unsubscribe(url)?;
+        }
+        Ok(())
+    }
+    /// The subscribers to notify and what to tell them, once the tenant's log has grown by
+    /// `notify_delta` since they were last notified.
+    fn due_notice(&mut self, tenant: &str) -> Option<(Vec<String>, Notice)> {
+        let notify_delta = self.notify_delta;
+        let tenant = self.tenants.get_mut(tenant)?;
+        let seq = tenant.seq.load(Ordering::SeqCst);
+        if self.subscribers.is_empty() || seq < tenant.notified + notify_delta {
+            return None;
+        }
+        tenant.notified = seq;
+        Some((self.subscribers.iter().cloned().collect(), Notice { seq }))
+    }
+}
+
+/// Notifies the subscribers in the background if the tenant's nouns changed enough. Called
+/// with the state locked for writing, right after a change.
+fn notify(data: &web::Data<RwLock<State>>, state: &mut State, tenant: &str) {
+    if let Some((urls, notice)) = state.due_notice(tenant) {
+        actix_web::rt::spawn(notify_subscribers(
+            data.clone(),
+            tenant.to_string(),
+            urls,
+            notice,
+        ));
+    }
+}
+
+/// Posts the notice to every subscriber. Subscribers that can't take [`NOTIFY_FAILURES`] in a
+/// row are dropped; a tokenizer subscribes again each time it syncs.
+async fn notify_subscribers(
+    data: web::Data<RwLock<State>>,
+    tenant: String,
+    urls: Vec<String>,
+    notice: Notice,
+) {
+    let client = awc::Client::default();
+    for subscriber in urls {
+        let url = format!(
+            "{}{}",
+            subscriber,
+            tenant::route(&tenant, changes::NOTIFY_ROUTE)
+        );
+        // A tokenizer without the tenant answers 404, which still shows it is there.
+        let failed = match client.post(&url).send_json(&notice).await {
+            Ok(res) if res.status().is_success() || res.status().as_u16() == 404 => None,
+            Ok(res) => Some(res.status().to_string()),
+            Err(err) => Some(err.to_string()),
+        };
+        match failed {
+            None => data.write().await.notice_taken(&subscriber),
+            Some(err) => {
+                println!("ERROR: notify {}: {}", url, err);
+                if let Err(err) = data.write().await.notice_failed(&subscriber) {
+                    println!("ERROR: {}", err);
+                }
+            }
+        }
     }
 }
 
+/// 404 for a tenant that has nothing yet.
+fn unknown_tenant(tenant: &str) -> Error {
+    Error::not_found(format!("unknown tenant: {}", tenant))
+}
+
 #[post("/train")]
-async fn train(bytes: web::Bytes, state: web::Data<RwLock<State>>) -> Result<String, Error> {
-    let lines = String::from_utf8(bytes.to_vec()).map_err(anyhow::Error::from)?;
-    Ok(format!("{:?}", state.write().await.train(lines)?))
+async fn train(
+    req: HttpRequest,
+    bytes: web::Bytes,
+    state: web::Data<RwLock<State>>,
+) -> Result<String, Error> {
+    let tenant = tenant::of(&req);
+    let lines = String::from_utf8(bytes.to_vec())
+        .map_err(|err| Error::bad_request(format!("body is not UTF-8: {}", err)))?;
+    metrics::TRAINED_BYTES
+        .with_label_values(&[&tenant])
+        .inc_by(lines.len() as u64);
+    metrics::TRAINED_LINES
+        .with_label_values(&[&tenant])
+        .inc_by(lines.lines().count() as u64);
+    let mut locked = state.write().await;
+    let timer = metrics::TRAIN_DURATION
+        .with_label_values(&[&tenant])
+        .start_timer();
+    let count = locked.train(&tenant, lines)?;
+    timer.observe_duration();
+    notify(&state, &mut locked, &tenant);
+    Ok(format!("{:?}", count))
 }
 
 #[get("/nouns")]
-async fn nouns(state: web::Data<RwLock<State>>) -> Result<HttpResponse, Error> {
-    Ok(HttpResponse::Ok().json(state.read().await.nouns()))
+async fn nouns(req: HttpRequest, state: web::Data<RwLock<State>>) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    Ok(match state.read().await.tenant(&tenant) {
+        Some(state) => HttpResponse::Ok()
+            .insert_header((
+                changes::SEQ_HEADER,
+                state.seq.load(Ordering::SeqCst).to_string(),
+            ))
+            .json(state.nouns()?),
+        None => return Err(unknown_tenant(&tenant)),
+    })
+}
+
+#[derive(Deserialize)]
+struct ChangesQuery {
+    #[serde(default)]
+    since: u64,
+    limit: Option<usize>,
+}
+#[get("/nouns/changes")]
+async fn noun_changes(
+    req: HttpRequest,
+    state: web::Data<RwLock<State>>,
+    query: web::Query<ChangesQuery>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let limit = query
+        .limit
+        .unwrap_or(changes::PAGE_SIZE)
+        .clamp(1, changes::PAGE_SIZE);
+    Ok(match state.read().await.tenant(&tenant) {
+        Some(state) => HttpResponse::Ok().json(state.changes(query.since, limit)?),
+        None => return Err(unknown_tenant(&tenant)),
+    })
 }
 
 #[derive(Deserialize)]
@@ -150,12 +727,108 @@ struct ScoreQuery {
 }
 #[get("/noun-score")]
 async fn noun_score(
+    req: HttpRequest,
     state: web::Data<RwLock<State>>,
     query: web::Query<ScoreQuery>,
 ) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
     let noun = query.into_inner().noun;
-    let score = state.read().await.noun_score(&noun)?;
-    Ok(HttpResponse::Ok().json(score))
+    Ok(match state.read().await.tenant(&tenant) {
+        Some(state) => HttpResponse::Ok().json(state.noun_score(&noun)?),
+        None => return Err(unknown_tenant(&tenant)),
+    })
+}
+
+#[get("/blocklist")]
+async fn blocklist(
+    req: HttpRequest,
+    state: web::Data<RwLock<State>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    Ok(match state.read().await.tenant(&tenant) {
+        Some(state) => HttpResponse::Ok().json(state.blocked()?),
+        None => return Err(unknown_tenant(&tenant)),
+    })
+}
+
+/// Path parameters are taken by name, since tenant-scoped routes carry the tenant as well.
+#[derive(Deserialize)]
+struct WordPath {
+    word: String,
+}
+
+#[put("/blocklist/{word}")]
+async fn block(
+    req: HttpRequest,
+    path: web::Path<WordPath>,
+    state: web::Data<RwLock<State>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let mut locked = state.write().await;
+    locked.open_tenant(&tenant)?.block(&path.word)?;
+    notify(&state, &mut locked, &tenant);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/blocklist/{word}")]
+async fn unblock(
+    req: HttpRequest,
+    path: web::Path<WordPath>,
+    state: web::Data<RwLock<State>>,
+) -> Result<HttpResponse, Error> {
+    let tenant = tenant::of(&req);
+    let mut locked = state.write().await;
+    let unblocked = match locked.tenant(&tenant) {
+        Some(tenant) => tenant.unblock(&path.word)?,
+        None => false,
+    };
+    if !unblocked {
+        return Err(Error::not_found(format!("not blocked: {}", path.word)));
+    }
+    notify(&state, &mut locked, &tenant);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/subscribers")]
+async fn subscribers(state: web::Data<RwLock<State>>) -> impl Responder {
+    HttpResponse::Ok().json(&state.read().await.subscribers)
+}
+
+#[derive(Deserialize)]
+struct SubscriberQuery {
+    url: String,
+}
+
+/// Subscribes a tokenizer, by the URL it serves its routes under, to notices of changed nouns
+/// for every tenant.
+#[put("/subscribers")]
+async fn subscribe(
+    query: web::Query<SubscriberQuery>,
+    state: web::Data<RwLock<State>>,
+) -> Result<HttpResponse, Error> {
+    if !is_http_url(&query.url) {
+        return Err(Error::bad_request(format!(
+            "not an http URL: {}",
+            query.url
+        )));
+    }
+    Ok(if state.write().await.subscribe(&query.url)? {
+        HttpResponse::Created().finish()
+    } else {
+        HttpResponse::NoContent().finish()
+    })
+}
+
+#[delete("/subscribers")]
+async fn unsubscribe(
+    query: web::Query<SubscriberQuery>,
+    state: web::Data<RwLock<State>>,
+) -> Result<HttpResponse, Error> {
+    if state.write().await.unsubscribe(&query.url)? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(Error::not_found(format!("not subscribed: {}", query.url)))
+    }
 }
 
 #[get("/health")]
@@ -163,6 +836,27 @@ async fn health() -> impl Responder {
     "ok"
 }
 
+/// Prometheus metrics, with the RocksDB sizes of each open tenant read at scrape time.
+#[get("/metrics")]
+async fn export_metrics(state: web::Data<RwLock<State>>) -> Result<HttpResponse, Error> {
+    for (name, tenant) in state.read().await.tenants.iter() {
+        metrics::LEARNED_NOUNS
+            .with_label_values(&[name])
+            .set(tenant.nouns.property("rocksdb.estimate-num-keys")? as i64);
+        for (keyspace, space) in tenant.keyspaces().iter() {
+            metrics::ROCKSDB_SST_BYTES
+                .with_label_values(&[name, keyspace])
+                .set(space.property("rocksdb.total-sst-files-size")? as i64);
+            metrics::ROCKSDB_MEMTABLE_BYTES
+                .with_label_values(&[name, keyspace])
+                .set(space.property("rocksdb.cur-size-all-mem-tables")? as i64);
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics::render()?))
+}
+
 #[derive(Deserialize)]
 struct SetThresholdQuery {
     unique_suffixes_count: f64,
@@ -172,16 +866,32 @@ struct SetThresholdQuery {
 
 #[post("/set-threshold")]
 async fn set_threshold(
+    req: HttpRequest,
     query: web::Json<SetThresholdQuery>,
     state: web::Data<RwLock<State>>,
-) -> impl Responder {
+) -> Result<impl Responder, Error> {
     let query = query.into_inner();
     state.write().await.set_threshold(
-        query.unique_suffixes_count,
-        query.count,
-        query.noun_probability,
-    );
-    "done"
+        &tenant::of(&req),
+        Thresholds {
+            unique_suffixes_count: query.unique_suffixes_count,
+            count: query.count,
+            noun_probability: query.noun_probability,
+        },
+    )?;
+    Ok("done")
+}
+
+/// Services every tenant has, mounted at the root and under [`tenant::SCOPE`].
+fn tenant_services(cfg: &mut web::ServiceConfig) {
+    cfg.service(train)
+        .service(nouns)
+        .service(noun_changes)
+        .service(set_threshold)
+        .service(noun_score)
+        .service(blocklist)
+        .service(block)
+        .service(unblock);
 }
 
 #[actix_web::main]
@@ -190,8 +900,6 @@ async fn main() -> anyhow::Result<()> {
     let noun_extractor_model_path = std::env::var("NOUN_EXTRACTOR_MODEL_PATH")
         .unwrap_or_else(|_| "noun-extractor-model".to_string());
     let store_path = std::env::var("STORE_PATH").unwrap_or_else(|_| "store".to_string());
-    let state = State::open(noun_extractor_model_path, store_path)?;
-    let data = web::Data::new(RwLock::new(state));
     let unique_suffixes_count_threshold: f64 = std::env::var("UNIQUE_SUFFIXES_COUNT_THRESHOLD")
         .unwrap_or_else(|_| "5.0".to_string())
         .parse()?;
@@ -201,11 +909,25 @@ async fn main() -> anyhow::Result<()> {
     let noun_probability_threshold: f32 = std::env::var("NOUN_PROBABILITY_THRESHOLD")
         .unwrap_or_else(|_| "0.9".to_string())
         .parse()?;
-    data.write().await.set_threshold(
-        unique_suffixes_count_threshold,
-        count_threshold,
-        noun_probability_threshold,
-    );
+    let notify_delta: u64 = std::env::var("NOTIFY_DELTA")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()?;
+    let changes_kept: u64 = match std::env::var("CHANGES_KEPT") {
+        Ok(kept) => kept.parse()?,
+        Err(_) => CHANGES_KEPT,
+    };
+    let state = State::open(
+        noun_extractor_model_path,
+        store_path,
+        Thresholds {
+            unique_suffixes_count: unique_suffixes_count_threshold,
+            count: count_threshold,
+            noun_probability: noun_probability_threshold,
+        },
+        notify_delta,
+        changes_kept,
+    )?;
+    let data = web::Data::new(RwLock::new(state));
 
     Ok(HttpServer::new(move || {
         let data = data.clone();
@@ -213,11 +935,16 @@ async fn main() -> anyhow::Result<()> {
             .app_data(data)
             .app_data(web::PayloadConfig::new(1024 * 1024 * 1024))
             .app_data(web::Bytes::configure(|cfg| cfg.limit(1024 * 1024 * 1024)))
-            .service(train)
-            .service(nouns)
+            .configure(error::extractors)
+            .wrap(error::RequestId)
+            .wrap(metrics::Metrics)
             .service(health)
-            .service(set_threshold)
-            .service(noun_score)
+            .service(export_metrics)
+            .service(subscribers)
+            .service(subscribe)
+            .service(unsubscribe)
+            .configure(tenant_services)
+            .service(web::scope(tenant::SCOPE).configure(tenant_services))
     })
     .bind(&format!("0.0.0.0:{}", port))?
     .run()
@@ -234,12 +961,26 @@ mod tests {
             let noun_extractor_path =
                 std::env::var("NOUN_EXTRACTOR_PATH").expect("NOUN_EXTRACTOR_PATH");
             let scores_store_path = std::env::var("SCORES_STORE_PATH").expect("SCORES_STORE_PATH");
-            let state = State::open(noun_extractor_path, scores_store_path).unwrap();
+            let state = State::open(
+                noun_extractor_path,
+                scores_store_path,
+                Thresholds::default(),
+                1,
+                CHANGES_KEPT,
+            )
+            .unwrap();
             App::new()
                 .app_data(web::Data::new(RwLock::new(state)))
-                .service(train)
-                .service(nouns)
+                .configure(error::extractors)
+                .wrap(error::RequestId)
+                .wrap(metrics::Metrics)
                 .service(health)
+                .service(export_metrics)
+                .service(subscribers)
+                .service(subscribe)
+                .service(unsubscribe)
+                .configure(tenant_services)
+                .service(web::scope(tenant::SCOPE).configure(tenant_services))
         })
     }
     #[actix_rt::test]
@@ -256,6 +997,323 @@ mod tests {
             "안녕\tIC,*,T,안녕,*,*,*,*\nEOS\n".to_string()
         );
     }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_blocklist() {
+        let srv = test_server();
+
+        let res = srv
+            .post("/set-threshold")
+            .send_json(&serde_json::json!({
+                "unique_suffixes_count": 0.0,
+                "count": 0,
+                "noun_probability": 0.0
+            }))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = srv
+            .put("/blocklist/%EA%B0%90%EC%8A%A4%ED%8A%B8")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv
+            .post("/train")
+            .send_body("감스트가 감스트를 감스트는 감스트의 감스트도 감스트에")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let mut res = srv.get("/nouns").send().await.unwrap();
+        let learned: Vec<String> = res.json().await.unwrap();
+        assert!(!learned.contains(&"감스트".to_string()));
+        let mut res = srv.get("/blocklist").send().await.unwrap();
+        let blocked: Vec<String> = res.json().await.unwrap();
+        assert!(blocked.contains(&"감스트".to_string()));
+
+        let res = srv
+            .delete("/blocklist/%EA%B0%90%EC%8A%A4%ED%8A%B8")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let res = srv
+            .delete("/blocklist/%EA%B0%90%EC%8A%A4%ED%8A%B8")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_noun_changes() {
+        let srv = test_server();
+
+        let res = srv
+            .post("/set-threshold")
+            .send_json(&serde_json::json!({
+                "unique_suffixes_count": 0.0,
+                "count": 0,
+                "noun_probability": 0.0
+            }))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = srv.get("/nouns").send().await.unwrap();
+        let since: u64 = res
+            .headers()
+            .get(changes::SEQ_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let res = srv
+            .post("/train")
+            .send_body("톩쒧이 톩쒧을 톩쒧은 톩쒧의 톩쒧도 톩쒧에")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let mut res = srv
+            .get(format!("/nouns/changes?since={}", since))
+            .send()
+            .await
+            .unwrap();
+        let page: changes::Page = res.json().await.unwrap();
+        assert!(page.last > since);
+        assert!(page
+            .changes
+            .iter()
+            .any(|change| change.op == Op::Add && change.noun == "톩쒧"));
+
+        let res = srv
+            .put("/blocklist/%ED%86%A9%EC%92%A7")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+        let mut res = srv
+            .get(format!("/nouns/changes?since={}&limit=1", page.last))
+            .send()
+            .await
+            .unwrap();
+        let page: changes::Page = res.json().await.unwrap();
+        assert!(!page.more);
+        assert_eq!(page.changes.len(), 1);
+        assert_eq!(page.changes[0].op, Op::Remove);
+        assert_eq!(page.changes[0].noun, "톩쒧");
+        let res = srv
+            .delete("/blocklist/%ED%86%A9%EC%92%A7")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 204);
+    }
+    #[test]
+    #[serial]
+    fn test_truncated_changes() {
+        let store_path = std::env::var("SCORES_STORE_PATH").expect("SCORES_STORE_PATH");
+        let tenant = Tenant::open(
+            Path::new(&store_path).join("truncated-changes"),
+            Thresholds::default(),
+            2,
+        )
+        .unwrap();
+        for noun in &["톩", "쒧", "뤣"] {
+            tenant.remove_noun(noun).unwrap();
+            tenant.add_noun(noun).unwrap();
+        }
+        let last = tenant.seq.load(Ordering::SeqCst);
+        let page = tenant.changes(0, changes::PAGE_SIZE).unwrap();
+        assert_eq!(page.first, last - 1);
+        assert_eq!(page.last, last);
+        assert_eq!(
+            page.changes
+                .iter()
+                .map(|change| change.seq)
+                .collect::<Vec<_>>(),
+            vec![last - 1, last]
+        );
+        assert_eq!(page.changes[1].noun, "뤣");
+        assert!(tenant.nouns().unwrap().contains(&"뤣".to_string()));
+        drop(tenant);
+
+        let tenant = Tenant::open(
+            Path::new(&store_path).join("truncated-changes"),
+            Thresholds::default(),
+            1,
+        )
+        .unwrap();
+        let page = tenant.changes(0, changes::PAGE_SIZE).unwrap();
+        assert_eq!(page.first, last);
+        assert_eq!(page.changes.len(), 1);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_errors() {
+        let srv = test_server();
+
+        let mut res = srv
+            .put("/subscribers?url=ftp%3A%2F%2Ftokenizer")
+            .insert_header((error::REQUEST_ID_HEADER, "req-1"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        assert_eq!(
+            res.headers().get(error::REQUEST_ID_HEADER).unwrap(),
+            "req-1"
+        );
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "bad_request");
+        assert_eq!(body.request_id.as_deref(), Some("req-1"));
+
+        let mut res = srv.get("/t/nobody/nouns").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        let request_id = res
+            .headers()
+            .get(error::REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "not_found");
+        assert_eq!(body.message, "unknown tenant: nobody");
+        assert_eq!(body.request_id, Some(request_id));
+
+        let mut res = srv.get("/nouns/changes?since=x").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "bad_request");
+        let mut res = srv
+            .post("/t/nobody/train")
+            .send_body(vec![0xff, 0xfe])
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "bad_request");
+        // Over the default payload limit, which actix refuses before the handler runs.
+        let mut res = srv
+            .post("/t/nobody/train")
+            .insert_header((error::REQUEST_ID_HEADER, "req-2"))
+            .send_body("가".repeat(100 * 1024))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 413);
+        let body: error::ErrorBody = res.json().await.unwrap();
+        assert_eq!(body.code, "payload_too_large");
+        assert_eq!(body.request_id.as_deref(), Some("req-2"));
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_subscribers() {
+        let srv = test_server();
+
+        let res = srv
+            .put("/subscribers?url=ftp%3A%2F%2Ftokenizer")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400);
+        let res = srv
+            .put("/subscribers?url=http%3A%2F%2F127.0.0.1%3A1%2F")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 201);
+        let mut res = srv.get("/subscribers").send().await.unwrap();
+        let subscribed: Vec<String> = res.json().await.unwrap();
+        assert_eq!(subscribed, vec!["http://127.0.0.1:1"]);
+
+        let res = srv
+            .post("/set-threshold")
+            .send_json(&serde_json::json!({
+                "unique_suffixes_count": 0.0,
+                "count": 0,
+                "noun_probability": 0.0
+            }))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        // Nothing listens there, so every notice fails. The subscriber outlasts the first ones
+        // and is dropped after NOTIFY_FAILURES in a row.
+        let words = ["쀍뛝", "뛝쀍", "쀍쀍"];
+        for (i, word) in words.iter().enumerate() {
+            let res = srv
+                .post("/train")
+                .send_body(format!("{0}이 {0}을 {0}은 {0}의 {0}도 {0}에", word))
+                .await
+                .unwrap();
+            assert!(res.status().is_success());
+            if i + 1 < NOTIFY_FAILURES as usize {
+                actix_rt::time::sleep(std::time::Duration::from_millis(500)).await;
+                let mut res = srv.get("/subscribers").send().await.unwrap();
+                let subscribed: Vec<String> = res.json().await.unwrap();
+                assert_eq!(subscribed, vec!["http://127.0.0.1:1"]);
+            }
+        }
+        let mut subscribed = vec![String::new()];
+        for _ in 0..50 {
+            let mut res = srv.get("/subscribers").send().await.unwrap();
+            subscribed = res.json().await.unwrap();
+            if subscribed.is_empty() {
+                break;
+            }
+            actix_rt::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(subscribed.is_empty());
+        let res = srv
+            .delete("/subscribers?url=http%3A%2F%2F127.0.0.1%3A1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    #[actix_rt::test]
+    #[serial]
+    async fn test_tenants() {
+        let srv = test_server();
+
+        let res = srv
+            .post("/t/acme/set-threshold")
+            .send_json(&serde_json::json!({
+                "unique_suffixes_count": 0.0,
+                "count": 0,
+                "noun_probability": 0.0
+            }))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = srv
+            .post("/t/acme/train")
+            .send_body("뤣쉙퀡이 뤣쉙퀡을 뤣쉙퀡은 뤣쉙퀡의 뤣쉙퀡도 뤣쉙퀡에")
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let mut res = srv.get("/t/acme/nouns").send().await.unwrap();
+        let learned: Vec<String> = res.json().await.unwrap();
+        assert!(learned.contains(&"뤣쉙퀡".to_string()));
+        let mut res = srv.get("/nouns").send().await.unwrap();
+        let learned: Vec<String> = res.json().await.unwrap();
+        assert!(!learned.contains(&"뤣쉙퀡".to_string()));
+
+        let res = srv.get("/t/other/nouns").send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+
+        let mut res = srv.get("/metrics").send().await.unwrap();
+        assert!(res.status().is_success());
+        let body = String::from_utf8(res.body().await.unwrap().to_vec()).unwrap();
+        for series in &[
+            r#"userdic_trained_lines_total{tenant="acme"} "#,
+            r#"userdic_learned_nouns{tenant="acme"} "#,
+            r#"userdic_rocksdb_sst_bytes{keyspace="nouns",tenant="acme"} "#,
+            r#"http_requests_total{method="POST",route="/t/{tenant}/train",status="200"} "#,
+        ] {
+            assert!(body.contains(series), "{}", series);
+        }
+    }
     /*#[actix_rt::test]
     #[serial]
     async fn test_regist_nouns() {
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Part-of-speech tags of the mecab-ko-dic (Sejong based) tagset. Serialized as the plain tag
+/// name; names outside the tagset, such as the `*` placeholder, are kept in `Other`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Tag {
+    /// 일반 명사
+    NNG,
+    /// 고유 명사
+    NNP,
+    /// 의존 명사
+    NNB,
+    /// 단위를 나타내는 명사
+    NNBC,
+    /// 수사
+    NR,
+    /// 대명사
+    NP,
+    /// 동사
+    VV,
+    /// 형용사
+    VA,
+    /// 보조 용언
+    VX,
+    /// 긍정 지정사
+    VCP,
+    /// 부정 지정사
+    VCN,
+    /// 관형사
+    MM,
+    /// 일반 부사
+    MAG,
+    /// 접속 부사
+    MAJ,
+    /// 감탄사
+    IC,
+    /// 주격 조사
+    JKS,
+    /// 보격 조사
+    JKC,
+    /// 관형격 조사
+    JKG,
+    /// 목적격 조사
+    JKO,
+    /// 부사격 조사
+    JKB,
+    /// 호격 조사
+    JKV,
+    /// 인용격 조사
+    JKQ,
+    /// 보조사
+    JX,
+    /// 접속 조사
+    JC,
+    /// 선어말 어미
+    EP,
+    /// 종결 어미
+    EF,
+    /// 연결 어미
+    EC,
+    /// 명사형 전성 어미
+    ETN,
+    /// 관형형 전성 어미
+    ETM,
+    /// 체언 접두사
+    XPN,
+    /// 명사 파생 접미사
+    XSN,
+    /// 동사 파생 접미사
+    XSV,
+    /// 형용사 파생 접미사
+    XSA,
+    /// 어근
+    XR,
+    /// 마침표, 물음표, 느낌표
+    SF,
+    /// 줄임표
+    SE,
+    /// 여는 괄호
+    SSO,
+    /// 닫는 괄호
+    SSC,
+    /// 구분자
+    SC,
+    /// 기타 기호
+    SY,
+    /// 외국어
+    SL,
+    /// 한자
+    SH,
+    /// 숫자
+    SN,
+    /// 분석 불능
+    UNKNOWN,
+    Other(String),
+}
+
+const TAGS: [(Tag, &str); 44] = [
+    (Tag::NNG, "NNG"),
+    (Tag::NNP, "NNP"),
+    (Tag::NNB, "NNB"),
+    (Tag::NNBC, "NNBC"),
+    (Tag::NR, "NR"),
+    (Tag::NP, "NP"),
+    (Tag::VV, "VV"),
+    (Tag::VA, "VA"),
+    (Tag::VX, "VX"),
+    (Tag::VCP, "VCP"),
+    (Tag::VCN, "VCN"),
+    (Tag::MM, "MM"),
+    (Tag::MAG, "MAG"),
+    (Tag::MAJ, "MAJ"),
+    (Tag::IC, "IC"),
+    (Tag::JKS, "JKS"),
+    (Tag::JKC, "JKC"),
+    (Tag::JKG, "JKG"),
+    (Tag::JKO, "JKO"),
+    (Tag::JKB, "JKB"),
+    (Tag::JKV, "JKV"),
+    (Tag::JKQ, "JKQ"),
+    (Tag::JX, "JX"),
+    (Tag::JC, "JC"),
+    (Tag::EP, "EP"),
+    (Tag::EF, "EF"),
+    (Tag::EC, "EC"),
+    (Tag::ETN, "ETN"),
+    (Tag::ETM, "ETM"),
+    (Tag::XPN, "XPN"),
+    (Tag::XSN, "XSN"),
+    (Tag::XSV, "XSV"),
+    (Tag::XSA, "XSA"),
+    (Tag::XR, "XR"),
+    (Tag::SF, "SF"),
+    (Tag::SE, "SE"),
+    (Tag::SSO, "SSO"),
+    (Tag::SSC, "SSC"),
+    (Tag::SC, "SC"),
+    (Tag::SY, "SY"),
+    (Tag::SL, "SL"),
+    (Tag::SH, "SH"),
+    (Tag::SN, "SN"),
+    (Tag::UNKNOWN, "UNKNOWN"),
+];
+
+impl Tag {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Tag::Other(tag) => tag,
+            tag => TAGS.iter().find(|(t, _)| t == tag).map_or("", |(_, s)| s),
+        }
+    }
+    /// False for names outside the tagset, which the dictionary compiler has no context id for.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Tag::Other(_))
+    }
+    /// 체언: NNG, NNP, NNB, NNBC, NR, NP
+    pub fn is_noun(&self) -> bool {
+        matches!(
+            self,
+            Tag::NNG | Tag::NNP | Tag::NNB | Tag::NNBC | Tag::NR | Tag::NP
+        )
+    }
+    /// 용언: VV, VA, VX, VCP, VCN
+    pub fn is_predicate(&self) -> bool {
+        matches!(self, Tag::VV | Tag::VA | Tag::VX | Tag::VCP | Tag::VCN)
+    }
+    /// 수식언: MM, MAG, MAJ
+    pub fn is_modifier(&self) -> bool {
+        matches!(self, Tag::MM | Tag::MAG | Tag::MAJ)
+    }
+    /// 관계언: JKS, JKC, JKG, JKO, JKB, JKV, JKQ, JX, JC
+    pub fn is_particle(&self) -> bool {
+        matches!(
+            self,
+            Tag::JKS
+                | Tag::JKC
+                | Tag::JKG
+                | Tag::JKO
+                | Tag::JKB
+                | Tag::JKV
+                | Tag::JKQ
+                | Tag::JX
+                | Tag::JC
+        )
+    }
+    /// 어미: EP, EF, EC, ETN, ETM
+    pub fn is_ending(&self) -> bool {
+        matches!(self, Tag::EP | Tag::EF | Tag::EC | Tag::ETN | Tag::ETM)
+    }
+    /// 용언 파생 접미사: XSV, XSA
+    pub fn is_predicate_suffix(&self) -> bool {
+        matches!(self, Tag::XSV | Tag::XSA)
+    }
+    /// 접사와 어근: XPN, XSN, XSV, XSA, XR
+    pub fn is_affix(&self) -> bool {
+        matches!(self, Tag::XPN | Tag::XSN | Tag::XSV | Tag::XSA | Tag::XR)
+    }
+    /// 부호: SF, SE, SSO, SSC, SC, SY
+    pub fn is_symbol(&self) -> bool {
+        matches!(
+            self,
+            Tag::SF | Tag::SE | Tag::SSO | Tag::SSC | Tag::SC | Tag::SY
+        )
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(s: &str) -> Self {
+        TAGS.iter()
+            .find(|(_, tag)| *tag == s)
+            .map_or_else(|| Tag::Other(s.to_string()), |(t, _)| t.clone())
+    }
+}
+
+impl From<String> for Tag {
+    fn from(s: String) -> Self {
+        match Tag::from(s.as_str()) {
+            Tag::Other(_) => Tag::Other(s),
+            tag => tag,
+        }
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> Self {
+        match tag {
+            Tag::Other(tag) => tag,
+            tag => tag.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_roundtrip() {
+        for (tag, name) in TAGS.iter() {
+            assert_eq!(&Tag::from(*name), tag);
+            assert_eq!(tag.as_str(), *name);
+        }
+        assert_eq!(Tag::from("*"), Tag::Other("*".to_string()));
+        assert_eq!(
+            serde_json::to_string(&vec![Tag::NNP, Tag::Other("*".to_string())]).unwrap(),
+            "[\"NNP\",\"*\"]"
+        );
+        let tags: Vec<Tag> = serde_json::from_str("[\"JKS\",\"UNKNOWN\"]").unwrap();
+        assert_eq!(tags, vec![Tag::JKS, Tag::UNKNOWN]);
+    }
+    #[test]
+    fn tag_categories() {
+        assert!(Tag::NNBC.is_noun() && !Tag::VV.is_noun());
+        assert!(Tag::VCP.is_predicate() && !Tag::XSV.is_predicate());
+        assert!(Tag::JX.is_particle() && !Tag::EC.is_particle());
+        assert!(Tag::ETM.is_ending());
+        assert!(Tag::SF.is_symbol() && !Tag::SL.is_symbol());
+        assert!(Tag::UNKNOWN.is_known() && !Tag::from("NNQ").is_known());
+    }
+}
@@ -0,0 +1,58 @@
+//! Tenants share the servers but not their learned nouns or dictionaries. Every tenant-aware
+//! service is mounted at the root for the default tenant and again under [`SCOPE`], where the
+//! route names the tenant.
+use actix_web::HttpRequest;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the tenant-scoped routes, such as `/t/{tenant}/tokenize`. Names are ASCII
+/// letters, digits, `-` and `_`; anything else does not match the route.
+pub const SCOPE: &str = "/t/{tenant:[A-Za-z0-9_-]{1,64}}";
+/// Serves the unscoped routes. `/t/default/...` reaches it as well.
+pub const DEFAULT: &str = "default";
+/// Directories of the named tenants, under a server's data path.
+const TENANTS_DIR: &str = "tenants";
+
+/// The tenant a request is for.
+pub fn of(req: &HttpRequest) -> String {
+    req.match_info()
+        .get("tenant")
+        .unwrap_or(DEFAULT)
+        .to_string()
+}
+
+/// `route` as served for a tenant, e.g. `/t/acme/nouns` for `/nouns`.
+pub fn route(tenant: &str, route: &str) -> String {
+    if tenant == DEFAULT {
+        route.to_string()
+    } else {
+        format!("/t/{}{}", tenant, route)
+    }
+}
+
+/// Where a tenant keeps its data under `base`. The default tenant predates tenants and keeps
+/// using `base` itself.
+pub fn path<P: AsRef<Path>>(base: P, tenant: &str) -> PathBuf {
+    if tenant == DEFAULT {
+        base.as_ref().to_path_buf()
+    } else {
+        base.as_ref().join(TENANTS_DIR).join(tenant)
+    }
+}
+
+/// The default tenant and every tenant with data under `base`.
+pub fn list<P: AsRef<Path>>(base: P) -> anyhow::Result<Vec<String>> {
+    let mut tenants = vec![DEFAULT.to_string()];
+    let dir = base.as_ref().join(TENANTS_DIR);
+    if dir.exists() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    tenants.push(name.to_string());
+                }
+            }
+        }
+    }
+    tenants[1..].sort();
+    Ok(tenants)
+}
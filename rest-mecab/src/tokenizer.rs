@@ -1,58 +1,108 @@
+use crate::dictionary;
+use crate::metrics;
+use crate::tag::Tag;
+use crate::tenant;
+use crate::userdics::UserDics;
+use crate::versions::{self, Meta, Versions};
 use anyhow::{Error, Result};
-use hangul_normalize::{control_chars, derepeat, whitespace_less};
-use mecab::Tagger;
+use hangul_normalize::{NormalizeConfig, NormalizedText, SourceRange};
+use mecab::{Node, Tagger, MECAB_BOS_NODE, MECAB_EOS_NODE, MECAB_UNK_NODE};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+/// Parsed by a tagger for a new build before it is activated.
+const SMOKE_TEST: &[&str] = &[
+    "형태소 분석기 사전 점검 문장입니다.",
+    "아버지가방에들어가신다",
+    "갤럭시S20 사전예약 10% 할인!! ㅋㅋㅋ",
+    "",
+];
+/// More sentences for the smoke test, one per line, under the MeCab dic path.
+pub const SMOKE_TEST_FILE: &str = "smoke-test.txt";
+
+/// Serializes builds, which share the CSVs under `user-dic/`.
+static BUILD_LOCK: Mutex<()> = Mutex::new(());
 
 pub fn has_support(c: char) -> bool {
     0xAC00 <= c as u32 && c as u32 <= 0xD7A3 && ((c as u32 - 0xAC00) % 28 != 0)
 }
-fn mecab_csv_nnp_format(nnp: &str) -> Result<String> {
-    if nnp.is_empty() {
-        return Err(Error::msg("NNP length 0"));
-    }
-    Ok(format!(
-        "{},,,,NNP,*,{},{},*,*,*,*",
-        nnp,
-        if has_support(nnp.chars().rev().next().unwrap()) {
-            "T"
-        } else {
-            "F"
-        },
-        nnp
-    ))
-}
-
 pub struct Tokenizer {
-    tagger: Tagger,
+    tagger: RefCell<Tagger>,
     mecab_dic_path: PathBuf,
+    userdic_path: PathBuf,
 }
 
-fn asterisk_as_none(s: String) -> Option<String> {
-    if s == "*" {
-        None
+/// Predicate stems, and the suffixes deriving them such as 하/XSV, take 다 in their
+/// dictionary form.
+fn lemma_of(token: &str, tag: &Tag) -> String {
+    if tag.is_predicate() || tag.is_predicate_suffix() {
+        format!("{}다", token)
     } else {
-        Some(s)
+        token.to_string()
     }
 }
 
+/// Whether `suffix` derives a predicate from `base`, as 하/XSV does from 공부/NNG and
+/// 하/XSA from 깨끗/XR.
+fn derives_predicate(base: &Tag, suffix: &Tag) -> bool {
+    (base.is_noun() || *base == Tag::XR) && suffix.is_predicate_suffix()
+}
+
+/// Location of a token in the text given to `Tokenizer::tokenize`, before any normalization.
+/// `start`/`end` count chars, `byte_start`/`byte_end` count UTF-8 bytes; both are end-exclusive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+/// Costs of a token on the path it was found on. `connection` is the cost of connecting it to
+/// the previous token, and `path` is the running total from the start of the sentence.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Costs {
+    pub word: i64,
+    pub connection: i64,
+    pub path: i64,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Morpheme {
-    token: String,
-    tag: String,
+    pub token: String,
+    pub tag: Tag,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Analytics {
     pub token: String,
-    pub tags: Vec<String>,
+    /// Dictionary form of the token, e.g. 먹다 for 먹 or 갔 (가/VV+았/EP), and 공부하다 for
+    /// 했 (하/XSV+았/EP) right after 공부.
+    #[serde(default)]
+    pub lemma: String,
+    pub tags: Vec<Tag>,
     pub symantic_group: Option<String>,
     pub has_support: Option<bool>,
     pub pronounce: Option<String>,
     pub kind: Option<String>,
-    pub left_tag: Option<String>,
-    pub right_tag: Option<String>,
+    pub left_tag: Option<Tag>,
+    pub right_tag: Option<Tag>,
     pub morphemes: Option<Vec<Morpheme>>,
+    /// Set when MeCab produced the token by unknown-word processing rather than a dictionary
+    /// entry.
+    #[serde(default)]
+    pub unknown: bool,
+    #[serde(default)]
+    pub span: Span,
+    #[serde(default)]
+    pub costs: Option<Costs>,
 }
 impl Analytics {
     fn parse_morphemes(s: &str, tag_lookup: Option<Vec<Morpheme>>) -> Result<Option<Vec<Morpheme>>> {
@@ -66,10 +116,12 @@ impl Analytics {
                     (Some(token), None, Some(lookup)) | (Some(token), Some("*"), Some(lookup)) => Ok(Morpheme {
                         token: token.to_string(),
                         tag: lookup.iter().find(|morph| morph.token == token).ok_or_else(|| anyhow::Error::msg(s.to_string()))?.tag.clone(),
+                        span: Span::default(),
                     }),
                     (Some(token), Some(tag), _) => Ok(Morpheme {
                         token: token.to_string(),
-                        tag: tag.to_string(),
+                        tag: Tag::from(tag),
+                        span: Span::default(),
                     }),
 
                     _ => Err(anyhow::Error::msg(s.to_string())),
@@ -78,159 +130,709 @@ impl Analytics {
             .collect::<Result<Vec<Morpheme>>>()?))
         }
     }
+    /// Parses one line of MeCab's default text output, `surface\tfeature`.
     pub fn parse(s: &str) -> Result<Self> {
-        let mut sp = s.split('\t');
-        let token = sp
-            .next()
-            .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-            .to_string();
-        let mut sp = sp
-            .next()
-            .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-            .split(',');
-        let tags = sp
-            .next()
-            .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-            .split('+')
-            .map(|s| s.to_string())
-            .collect();
-        let symantic_group = asterisk_as_none(
-            sp.next()
-                .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-                .to_string(),
-        );
-        let has_support = match sp.next().ok_or_else(|| anyhow::Error::msg(s.to_string()))? {
-            "T" => Some(true),
-            "F" => Some(false),
+        let mut sp = s.splitn(2, '\t');
+        match (sp.next(), sp.next()) {
+            (Some(surface), Some(feature)) => Ok(Analytics::from_feature(surface, feature, false)),
+            _ => Err(anyhow::Error::msg(s.to_string())),
+        }
+    }
+    /// Builds a token from a node's surface and its comma-separated feature array. Unknown
+    /// words often carry fewer than the eight mecab-ko-dic fields, so missing or malformed
+    /// fields become `None` rather than an error, and a missing tag becomes `UNKNOWN`.
+    pub fn from_feature(surface: &str, feature: &str, unknown: bool) -> Self {
+        let fields = feature.split(',').collect::<Vec<_>>();
+        let field = |i: usize| {
+            fields
+                .get(i)
+                .filter(|field| !field.is_empty() && **field != "*")
+                .map(|field| field.to_string())
+        };
+        let tags = match field(0) {
+            Some(tags) => tags.split('+').map(Tag::from).collect(),
+            None => vec![Tag::UNKNOWN],
+        };
+        let has_support = match fields.get(2) {
+            Some(&"T") => Some(true),
+            Some(&"F") => Some(false),
             _ => None,
         };
-        let pronounce = asterisk_as_none(
-            sp.next()
-                .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-                .to_string(),
-        );
-        let kind = asterisk_as_none(
-            sp.next()
-                .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-                .to_string(),
-        );
-        let left_tag = asterisk_as_none(
-            sp.next()
-                .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-                .to_string(),
-        );
-        let right_tag = asterisk_as_none(
-            sp.next()
-                .ok_or_else(|| anyhow::Error::msg(s.to_string()))?
-                .to_string(),
-        );
-        let morphemes_simple = sp.next().ok_or_else(|| anyhow::Error::msg(s.to_string()))?;
-        let morphemes = match sp.next() {
-            Some(morphemes_detail) => {
-                let tag_lookup = Analytics::parse_morphemes(morphemes_detail, None)?;
-                Analytics::parse_morphemes(morphemes_simple, tag_lookup)?
+        let morphemes = match (field(7), field(8)) {
+            (Some(morphemes_simple), Some(morphemes_detail)) => {
+                Analytics::parse_morphemes(&morphemes_detail, None).and_then(|tag_lookup| {
+                    Analytics::parse_morphemes(&morphemes_simple, tag_lookup)
+                })
             }
-            None => Analytics::parse_morphemes(morphemes_simple, None)?,
-        };
-        Ok(Analytics {
-            token,
+            (Some(morphemes_simple), None) => Analytics::parse_morphemes(&morphemes_simple, None),
+            _ => Ok(None),
+        }
+        // Some entries have an expression that does not parse, such as 그냥 for 걍 with no tags.
+        // The token itself is still right, so it is kept without morphemes.
+        .unwrap_or_default();
+        let kind = field(4);
+        let lemma = Analytics::lemmatize(surface, &tags, kind.as_deref(), morphemes.as_deref());
+        Analytics {
+            token: surface.to_string(),
+            lemma,
             tags,
-            symantic_group,
+            symantic_group: field(1),
             has_support,
-            pronounce,
+            pronounce: field(3),
             kind,
-            left_tag,
-            right_tag,
+            left_tag: field(5).map(Tag::from),
+            right_tag: field(6).map(Tag::from),
             morphemes,
+            unknown,
+            span: Span::default(),
+            costs: None,
+        }
+    }
+    /// Inflected tokens take the dictionary form of their first morpheme (갔 → 가다), or of
+    /// the stem derived from their leading morphemes (공부했 → 공부하다); anything else led by a
+    /// predicate tag gets 다 appended to the surface.
+    fn lemmatize(
+        token: &str,
+        tags: &[Tag],
+        kind: Option<&str>,
+        morphemes: Option<&[Morpheme]>,
+    ) -> String {
+        let derived = morphemes.and_then(|morphemes| {
+            let i = morphemes
+                .windows(2)
+                .position(|pair| derives_predicate(&pair[0].tag, &pair[1].tag))?;
+            let stem = morphemes[..i + 2]
+                .iter()
+                .map(|morpheme| morpheme.token.as_str())
+                .collect::<String>();
+            Some(format!("{}다", stem))
+        });
+        if let Some(lemma) = derived {
+            return lemma;
+        }
+        match (kind, morphemes.and_then(|morphemes| morphemes.first())) {
+            (Some("Inflect"), Some(head)) => lemma_of(&head.token, &head.tag),
+            _ => match tags.first() {
+                Some(tag) => lemma_of(token, tag),
+                None => token.to_string(),
+            },
+        }
+    }
+    /// Splits a compound or inflected token into one `Analytics` per morpheme; tokens without
+    /// morphemes are returned as they are.
+    pub fn expand(self) -> Vec<Analytics> {
+        match self.morphemes {
+            Some(morphemes) => morphemes
+                .into_iter()
+                .map(|morpheme| Analytics {
+                    lemma: lemma_of(&morpheme.token, &morpheme.tag),
+                    token: morpheme.token,
+                    tags: vec![morpheme.tag],
+                    symantic_group: None,
+                    has_support: None,
+                    pronounce: None,
+                    kind: None,
+                    left_tag: None,
+                    right_tag: None,
+                    morphemes: None,
+                    unknown: false,
+                    span: morpheme.span,
+                    costs: None,
+                })
+                .collect(),
+            None => vec![self],
+        }
+    }
+}
+
+/// Keeps tokens by their leading tag. Patterns are tag names such as `NNG`, or prefixes ending
+/// in `*` such as `NN*`; an empty include list keeps everything that isn't excluded.
+#[derive(Debug, Default, Clone)]
+pub struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+fn tag_matches(pattern: &str, tag: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => tag.starts_with(prefix),
+        None => pattern == tag,
+    }
+}
+
+impl TagFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+    /// Builds a filter from comma-separated pattern lists, e.g. `NN*,V*`.
+    pub fn parse(include: Option<&str>, exclude: Option<&str>) -> Self {
+        let split = |patterns: Option<&str>| {
+            patterns
+                .unwrap_or("")
+                .split(',')
+                .map(|pattern| pattern.trim().to_string())
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        };
+        Self::new(split(include), split(exclude))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| tag_matches(p, tag)))
+            && !self.exclude.iter().any(|p| tag_matches(p, tag))
+    }
+    pub fn matches(&self, analytics: &Analytics) -> bool {
+        match analytics.tags.first() {
+            Some(tag) => self.matches_tag(tag.as_str()),
+            None => self.include.is_empty(),
+        }
+    }
+}
+
+impl From<SourceRange> for Span {
+    fn from(range: SourceRange) -> Self {
+        Span {
+            start: range.chars.start,
+            end: range.chars.end,
+            byte_start: range.bytes.start,
+            byte_end: range.bytes.end,
+        }
+    }
+}
+
+/// Finds `token` in `text[*cursor..limit]` and advances the cursor past it. Returns the byte
+/// range of the match, or an empty range at the cursor if MeCab's surface can't be found
+/// verbatim.
+fn find_surface(text: &str, token: &str, cursor: &mut usize, limit: usize) -> Range<usize> {
+    match text[*cursor..limit].find(token) {
+        Some(i) => {
+            let start = *cursor + i;
+            *cursor = start + token.len();
+            start..*cursor
+        }
+        None => *cursor..*cursor,
+    }
+}
+
+fn assign_spans(normalized: &NormalizedText, analytics: &mut [Analytics]) {
+    let text = normalized.as_str();
+    let mut cursor = 0;
+    for analytic in analytics.iter_mut() {
+        let range = find_surface(text, &analytic.token, &mut cursor, text.len());
+        analytic.span = normalized.source_range(range.clone()).into();
+        if let Some(morphemes) = analytic.morphemes.as_mut() {
+            let mut morpheme_cursor = range.start;
+            for morpheme in morphemes.iter_mut() {
+                let morpheme_range =
+                    find_surface(text, &morpheme.token, &mut morpheme_cursor, range.end);
+                // Inflected morphemes such as 하/VV in 했 don't appear in the surface,
+                // so they get the span of the whole token.
+                morpheme.span = if morpheme_range.is_empty() {
+                    analytic.span
+                } else {
+                    normalized.source_range(morpheme_range).into()
+                };
+            }
+        }
+    }
+}
+
+/// Gives a 하/XSV or 하/XSA token written right after the noun or root it derives from the
+/// lemma of the derived predicate, so 했 in 공부/NNG 했/XSV+EP becomes 공부하다.
+fn join_derived_stems(analytics: &mut [Analytics]) {
+    for i in 1..analytics.len() {
+        let (before, after) = analytics.split_at_mut(i);
+        let (base, suffix) = (&before[i - 1], &mut after[0]);
+        let derived = match (base.tags.last(), suffix.tags.first()) {
+            (Some(base_tag), Some(suffix_tag)) => derives_predicate(base_tag, suffix_tag),
+            _ => false,
+        };
+        if derived && base.span.end == suffix.span.start {
+            suffix.lemma = format!("{}{}", base.token, suffix.lemma);
+        }
+    }
+}
+
+/// One of the N best analyses of a text, with the total cost of its path.
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    pub cost: i64,
+    pub analytics: Vec<Analytics>,
+}
+
+/// Word cost plus connection cost of reaching `node` from the node with id `prev_id`. MeCab
+/// only rewrites `Node::cost` along the best path, so N-best paths need the lattice edge.
+fn transition_cost(node: &Node, prev_id: u32) -> Option<i64> {
+    let mut path = node.lpath();
+    while let Some(edge) = path {
+        if edge.lnode().id == prev_id {
+            return Some(edge.cost as i64);
+        }
+        path = edge.lnext();
+    }
+    None
+}
+
+/// Walks a path from its BOS node, returning its tokens and the cost of the whole path.
+fn analytics_from_path(bos: Node) -> Result<(Vec<Analytics>, i64)> {
+    let mut analytics = Vec::new();
+    let (mut prev_id, mut prev_cost, mut path_cost) = (bos.id, bos.cost, 0);
+    for node in bos.iter_next() {
+        if node.stat as i32 == MECAB_BOS_NODE {
+            continue;
+        }
+        let transition = transition_cost(&node, prev_id).unwrap_or(node.cost - prev_cost);
+        path_cost += transition;
+        if node.stat as i32 == MECAB_EOS_NODE {
+            return Ok((analytics, path_cost));
+        }
+        let mut analytic = Analytics::from_feature(
+            &node.surface[..node.length as usize],
+            &node.feature,
+            node.stat as i32 == MECAB_UNK_NODE,
+        );
+        let word = node.wcost as i64;
+        analytic.costs = Some(Costs {
+            word,
+            connection: transition - word,
+            path: path_cost,
+        });
+        analytics.push(analytic);
+        prev_id = node.id;
+        prev_cost = node.cost;
+    }
+    Err(Error::msg("MeCab path ended without an EOS node"))
+}
+
+/// Writes the CSVs, snapshots them into a fresh build directory and compiles them there,
+/// then opens a tagger on the build and parses a probe sentence. Only a build that passes is
+/// activated; taggers pick it up on `reload`. `learned` replaces the learned nouns along with
+/// the change log sequence they reflect, which is recorded in the build's meta so the two
+/// activate together; `None` keeps both.
+fn build_userdic(
+    mecab_dic_path: &Path,
+    userdic_path: &Path,
+    learned: Option<(Vec<String>, Option<u64>)>,
+    source: Option<String>,
+) -> Result<()> {
+    let _guard = BUILD_LOCK
+        .lock()
+        .map_err(|_| Error::msg("a dictionary build panicked"))?;
+    let userdics = UserDics::new(userdic_path);
+    let (learned, learned_seq) = match learned {
+        Some((nouns, seq)) => (Some(nouns), seq),
+        None => (None, userdics.learned_seq()?),
+    };
+    userdics.write_csv(learned)?;
+    let versions = Versions::new(userdic_path);
+    let (version, dir) = versions.create()?;
+    let checked = versions
+        .snapshot(version, userdic_path.join("user-dic"))
+        .and_then(|_| userdics.save_sources(&versions.sources_dir(version)))
+        .and_then(|_| {
+            dictionary::compile_user_dic(
+                mecab_dic_path,
+                versions.csv_dir(version),
+                dir.join(dictionary::USER_DIC),
+            )
         })
+        .and_then(|entries| {
+            let args = tagger_args(mecab_dic_path, userdic_path, Some(version))?;
+            let tokenizer = Tokenizer {
+                tagger: RefCell::new(open_tagger(args)?),
+                mecab_dic_path: mecab_dic_path.to_path_buf(),
+                userdic_path: userdic_path.to_path_buf(),
+            };
+            smoke_test(&tokenizer, mecab_dic_path)?;
+            Ok(entries)
+        });
+    let entries = match checked {
+        Ok(entries) => entries,
+        Err(err) => {
+            versions.remove(version)?;
+            return Err(Error::msg(format!(
+                "dictionary version {}: {}",
+                version, err
+            )));
+        }
+    };
+    versions.write_meta(&Meta {
+        version,
+        created_at: versions::now(),
+        entries,
+        source,
+        hash: Some(versions.hash(version)?),
+        learned_seq,
+    })?;
+    versions.activate(version)?;
+    if let Some(keep) = keep_versions() {
+        versions.prune(keep)?;
+    }
+    Ok(())
+}
+
+/// Every build is kept unless `USERDIC_KEEP_VERSIONS` is set. Each holds a full copy of the
+/// user dictionary, so large dictionaries want it set; builds older than the newest that many
+/// are then removed and can no longer be rolled back to.
+fn keep_versions() -> Option<usize> {
+    std::env::var("USERDIC_KEEP_VERSIONS")
+        .ok()
+        .and_then(|keep| keep.parse().ok())
+        .filter(|keep| *keep > 0)
+}
+
+/// [`Tokenizer::gen_userdic`] on the blocking pool for the user dictionaries under
+/// `userdic_path`, without opening a tagger on them.
+pub async fn gen_userdic_async(
+    mecab_dic_path: &Path,
+    userdic_path: &Path,
+    nouns: Vec<String>,
+    seq: Option<u64>,
+    source: Option<String>,
+) -> Result<()> {
+    let (path, userdic_path) = (mecab_dic_path.to_path_buf(), userdic_path.to_path_buf());
+    blocking::unblock(move || build_userdic(&path, &userdic_path, Some((nouns, seq)), source)).await
+}
+
+/// Makes `version` the active build again and puts back the sources it was written from, so
+/// later edits and syncs start from it. Its meta holds the learned nouns' sequence, so the
+/// changes the userdic server logged after it are pulled again by the next sync.
+fn rollback_userdic(userdic_path: &Path, version: u64) -> Result<Meta> {
+    let _guard = BUILD_LOCK
+        .lock()
+        .map_err(|_| Error::msg("a dictionary build panicked"))?;
+    let versions = Versions::new(userdic_path);
+    let sources = versions.sources_dir(version);
+    if !sources.exists() {
+        return Err(Error::msg(format!(
+            "dictionary version {} has no sources to restore",
+            version
+        )));
+    }
+    UserDics::new(userdic_path).restore_sources(&sources, &versions.csv_dir(version))?;
+    versions.activate(version)?;
+    versions.meta(version)
+}
+
+/// Fails unless every sentence of the smoke test corpus parses to an EOS.
+fn smoke_test(tokenizer: &Tokenizer, mecab_dic_path: &Path) -> Result<()> {
+    let path = mecab_dic_path.join(SMOKE_TEST_FILE);
+    let extra = if path.exists() {
+        std::fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+    for sentence in SMOKE_TEST.iter().copied().chain(extra.lines()) {
+        tokenizer
+            .tokenize(sentence)
+            .map_err(|err| Error::msg(format!("smoke test {:?}: {}", sentence, err)))?;
+    }
+    Ok(())
+}
+
+/// A dictionary option of the tagger. MeCab splits its arguments at whitespace and has no
+/// quoting, so a path with whitespace is refused rather than loading the wrong dictionary.
+fn dictionary_arg(flag: &str, path: &Path) -> Result<String> {
+    let arg = path
+        .to_str()
+        .ok_or_else(|| Error::msg(format!("dictionary path is not UTF-8: {}", path.display())))?;
+    if arg.contains(char::is_whitespace) {
+        return Err(Error::msg(format!(
+            "MeCab can't load a dictionary from a path with whitespace: {:?}",
+            arg
+        )));
+    }
+    Ok(format!("{} {}", flag, arg))
+}
+
+/// Loads the system dictionary under `mecab_dic_path` and the user dictionary of `version`
+/// under `userdic_path`, if it has one. With an empty path the system dictionary is whatever
+/// mecabrc names.
+fn tagger_args(mecab_dic_path: &Path, userdic_path: &Path, version: Option<u64>) -> Result<String> {
+    let mut args = Vec::new();
+    if !mecab_dic_path.as_os_str().is_empty() {
+        args.push(dictionary_arg("-d", mecab_dic_path)?);
+    }
+    if let Some(version) = version {
+        let user_dic = Versions::new(userdic_path)
+            .dir(version)
+            .join(dictionary::USER_DIC);
+        if user_dic.exists() {
+            args.push(dictionary_arg("-u", &user_dic)?);
+        }
+    }
+    Ok(args.join(" "))
+}
+
+fn current_tagger_args(mecab_dic_path: &Path, userdic_path: &Path) -> Result<String> {
+    let version = Versions::new(userdic_path).current()?;
+    tagger_args(mecab_dic_path, userdic_path, version)
+}
+
+/// A tagger opened on another thread, on its way to the one that will use it.
+struct OpenedTagger(Tagger);
+
+// SAFETY: a MeCab tagger keeps no thread-local state. It is only moved, never shared, and the
+// thread that opened it drops every reference to it before handing it over.
+unsafe impl Send for OpenedTagger {}
+
+/// Falls back to the dictionary mecabrc names when the configured ones can't be passed.
+fn tagger_args_or_default(mecab_dic_path: &Path, userdic_path: &Path) -> String {
+    current_tagger_args(mecab_dic_path, userdic_path).unwrap_or_else(|err| {
+        println!("ERROR: opening the default dictionary instead: {:#}", err);
+        String::new()
+    })
+}
+
+/// MeCab hands back no tagger when it cannot load a dictionary; its error message is then
+/// the global one, and a tagger that loaded has none.
+fn open_tagger(args: String) -> Result<Tagger> {
+    let tagger = Tagger::new(args);
+    let err = tagger.get_last_error();
+    if err.is_empty() {
+        Ok(tagger)
+    } else {
+        Err(Error::msg(err))
     }
 }
 
 impl Tokenizer {
     pub fn new<P: AsRef<Path>>(mecab_dic_path: P) -> Self {
-        let tagger = Tagger::new("");
+        Self::with_userdic_path(mecab_dic_path.as_ref(), mecab_dic_path.as_ref())
+    }
+    /// A tokenizer on the system dictionary under `mecab_dic_path` whose user dictionaries,
+    /// their sources and builds live under `userdic_path`, so that several can share one
+    /// system dictionary.
+    pub fn with_userdic_path<P: AsRef<Path>, Q: AsRef<Path>>(
+        mecab_dic_path: P,
+        userdic_path: Q,
+    ) -> Self {
+        let (mecab_dic_path, userdic_path) = (mecab_dic_path.as_ref(), userdic_path.as_ref());
+        let args = tagger_args_or_default(mecab_dic_path, userdic_path);
         Self {
-            tagger,
-            mecab_dic_path: mecab_dic_path.as_ref().to_path_buf(),
+            tagger: RefCell::new(Tagger::new(args)),
+            mecab_dic_path: mecab_dic_path.to_path_buf(),
+            userdic_path: userdic_path.to_path_buf(),
         }
     }
+    /// A fresh tokenizer on the same dictionaries, loading the active build. Loading takes a
+    /// while, so it runs on the blocking pool. Fails instead of handing out a tagger that could
+    /// not load it.
+    pub async fn reopen(&self) -> Result<Self> {
+        let (mecab_dic_path, userdic_path) =
+            (self.mecab_dic_path.clone(), self.userdic_path.clone());
+        let tagger = blocking::unblock(move || {
+            let args = current_tagger_args(&mecab_dic_path, &userdic_path)?;
+            open_tagger(args).map(OpenedTagger)
+        })
+        .await?;
+        Ok(Self {
+            tagger: RefCell::new(tagger.0),
+            mecab_dic_path: self.mecab_dic_path.clone(),
+            userdic_path: self.userdic_path.clone(),
+        })
+    }
     pub fn tokenize(&self, q: &str) -> Result<Vec<Analytics>> {
-        let s = control_chars(q, "_");
-        let s = whitespace_less(&s);
-        let s = derepeat(&s, 3);
-        self.tagger
-            .parse_str(s)
-            .lines()
-            .filter_map(|l| {
-                if l != "EOS" {
-                    Some(Analytics::parse(l))
-                } else {
-                    None
-                }
-            })
-            .collect()
+        self.tokenize_with(q, &NormalizeConfig::default())
     }
-    pub fn get_userdic(&self) -> Result<Vec<u8>> {
-        let path = self.mecab_dic_path.clone();
-        let userdic_path = Path::new(&path).join("user-dic/rest-mecab.csv");
-        Ok(std::fs::read(userdic_path)?)
-    }
-    pub fn gen_userdic(&self, nouns: Vec<String>) -> Result<()> {
-        let path = self.mecab_dic_path.clone();
-        let userdic_path = Path::new(&path).join("user-dic/rest-mecab.csv");
-        std::fs::write(
-            userdic_path,
-            nouns
-                .into_iter()
-                .filter_map(|n| mecab_csv_nnp_format(&n).ok())
-                .collect::<Vec<_>>()
-                .join("\n"),
-        )?;
-        let output = std::process::Command::new("bash")
-            .current_dir(Path::new(&path))
-            .args(&["-c", r#"./tools/add-userdic.sh && make && make install"#])
-            .output()?;
-        if !output.status.success() {
-            return Err(anyhow::Error::msg(format!(
-                "Status: {:?}, Stdout: {:?}, Stderr: {:?}",
-                output.status,
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
-            )));
+    pub fn tokenize_with(&self, q: &str, normalize: &NormalizeConfig) -> Result<Vec<Analytics>> {
+        let normalized = normalize.apply_aligned(q);
+        let bos = self.tagger.borrow_mut().parse_to_node(normalized.as_str());
+        let (mut analytics, _) = analytics_from_path(bos)?;
+        assign_spans(&normalized, &mut analytics);
+        join_derived_stems(&mut analytics);
+        metrics::TOKENIZED_BYTES.inc_by(q.len() as u64);
+        metrics::TOKENS.inc_by(analytics.len() as u64);
+        Ok(analytics)
+    }
+    /// Up to `n` alternative analyses, cheapest first.
+    pub fn tokenize_nbest(
+        &self,
+        q: &str,
+        n: usize,
+        normalize: &NormalizeConfig,
+    ) -> Result<Vec<Candidate>> {
+        let normalized = normalize.apply_aligned(q);
+        let mut tagger = self.tagger.borrow_mut();
+        if !tagger.parse_nbest_init(normalized.as_str()) {
+            return Err(Error::msg("MeCab failed to start an N-best search"));
         }
-        Ok(())
+        let mut candidates = Vec::new();
+        while candidates.len() < n {
+            let bos = match tagger.next_node() {
+                Some(bos) => bos,
+                None => break,
+            };
+            let (mut analytics, cost) = analytics_from_path(bos)?;
+            assign_spans(&normalized, &mut analytics);
+            join_derived_stems(&mut analytics);
+            candidates.push(Candidate { cost, analytics });
+        }
+        metrics::TOKENIZED_BYTES.inc_by(q.len() as u64);
+        if let Some(best) = candidates.first() {
+            metrics::TOKENS.inc_by(best.analytics.len() as u64);
+        }
+        Ok(candidates)
     }
-    pub async fn gen_userdic_async(&self, nouns: Vec<String>) -> Result<()> {
-        let path = self.mecab_dic_path.clone();
-        blocking::unblock(move || -> Result<()> {
-            let userdic_path = Path::new(&path).join("user-dic/rest-mecab.csv");
-            std::fs::write(
-                userdic_path,
-                nouns
-                    .into_iter()
-                    .filter_map(|n| mecab_csv_nnp_format(&n).ok())
-                    .collect::<Vec<_>>()
-                    .join("\n"),
-            )?;
-            let output = std::process::Command::new("bash")
-                .current_dir(Path::new(&path))
-                .args(&["-c", r#"./tools/add-userdic.sh && make && make install"#])
-                .output()?;
-            if !output.status.success() {
-                return Err(anyhow::Error::msg(format!(
-                    "Status: {:?}, Stdout: {:?}, Stderr: {:?}",
-                    output.status,
-                    String::from_utf8_lossy(&output.stdout),
-                    String::from_utf8_lossy(&output.stderr)
-                )));
-            }
-            Ok(())
-        })
-        .await?;
-        Ok(())
+    /// CSV lines of the active user dictionary build.
+    pub fn userdic_entries(&self) -> Result<Vec<String>> {
+        let versions = self.versions();
+        match versions.current()? {
+            Some(version) => versions.entries(version),
+            None => Ok(Vec::new()),
+        }
+    }
+    pub fn mecab_dic_path(&self) -> &Path {
+        &self.mecab_dic_path
+    }
+    pub fn userdic_path(&self) -> &Path {
+        &self.userdic_path
+    }
+    pub fn userdics(&self) -> UserDics {
+        UserDics::new(&self.userdic_path)
+    }
+    pub fn versions(&self) -> Versions {
+        Versions::new(&self.userdic_path)
+    }
+    /// Replaces the learned nouns and builds a new user dictionary version with them, the
+    /// named dictionaries and the manual entries, recording `source` as where the nouns came
+    /// from and `seq` as where they are in its change log. Call `reload` to pick it up.
+    pub fn gen_userdic(
+        &self,
+        nouns: Vec<String>,
+        seq: Option<u64>,
+        source: Option<String>,
+    ) -> Result<()> {
+        build_userdic(
+            &self.mecab_dic_path,
+            &self.userdic_path,
+            Some((nouns, seq)),
+            source,
+        )
+    }
+    pub async fn gen_userdic_async(
+        &self,
+        nouns: Vec<String>,
+        seq: Option<u64>,
+        source: Option<String>,
+    ) -> Result<()> {
+        gen_userdic_async(&self.mecab_dic_path, &self.userdic_path, nouns, seq, source).await
+    }
+    /// Builds a new user dictionary version after named dictionaries or manual entries
+    /// changed, keeping the learned nouns as they are.
+    pub async fn rebuild_userdic_async(&self) -> Result<()> {
+        let (path, userdic_path) = (self.mecab_dic_path.clone(), self.userdic_path.clone());
+        blocking::unblock(move || build_userdic(&path, &userdic_path, None, None)).await
+    }
+    /// Rolls back to `version`, see [`rollback_userdic`].
+    pub async fn rollback_userdic_async(&self, version: u64) -> Result<Meta> {
+        let userdic_path = self.userdic_path.clone();
+        blocking::unblock(move || rollback_userdic(&userdic_path, version)).await
     }
     pub fn reload(&mut self) {
-        self.tagger = Tagger::new("");
+        let args = tagger_args_or_default(&self.mecab_dic_path, &self.userdic_path);
+        self.tagger = RefCell::new(Tagger::new(args));
+    }
+}
+
+/// A worker's tokenizer. Handlers take the current one and hold it for the whole request, so
+/// swapping in the tokenizer of a new build neither waits for them nor changes the dictionary
+/// under them.
+pub struct TokenizerSlot {
+    current: RefCell<Rc<Tokenizer>>,
+}
+
+impl TokenizerSlot {
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            current: RefCell::new(Rc::new(tokenizer)),
+        }
+    }
+    pub fn get(&self) -> Rc<Tokenizer> {
+        self.current.borrow().clone()
+    }
+    /// Opens the active build off the worker and swaps it in. On failure the current
+    /// tokenizer stays.
+    pub async fn reload(&self) -> Result<()> {
+        let timer = metrics::DICTIONARY_RELOAD_DURATION.start_timer();
+        let reopened = self.get().reopen().await;
+        timer.observe_duration();
+        metrics::DICTIONARY_RELOADS
+            .with_label_values(&[metrics::result_label(&reopened)])
+            .inc();
+        self.current.replace(Rc::new(reopened?));
+        Ok(())
+    }
+}
+
+/// A worker's tokenizers, one per tenant with data, opened on first use. All of them load the system
+/// dictionary under the MeCab dic path, each with its own user dictionary.
+pub struct Tenants {
+    mecab_dic_path: PathBuf,
+    slots: RefCell<HashMap<String, Rc<TokenizerSlot>>>,
+}
+
+impl Tenants {
+    pub fn new<P: AsRef<Path>>(mecab_dic_path: P) -> Self {
+        Self {
+            mecab_dic_path: mecab_dic_path.as_ref().to_path_buf(),
+            slots: RefCell::new(HashMap::new()),
+        }
+    }
+    /// The tenant's tokenizer, or `None` when the tenant has no data yet. The default tenant
+    /// always exists.
+    pub fn get(&self, tenant: &str) -> Option<Rc<TokenizerSlot>> {
+        if let Some(slot) = self.slots.borrow().get(tenant) {
+            return Some(slot.clone());
+        }
+        let path = tenant::path(&self.mecab_dic_path, tenant);
+        if tenant != tenant::DEFAULT && !path.is_dir() {
+            return None;
+        }
+        Some(self.open(tenant, path))
+    }
+    /// The tenant's tokenizer, making the tenant's data directory first if it has none.
+    pub fn create(&self, tenant: &str) -> Result<Rc<TokenizerSlot>> {
+        if let Some(slot) = self.get(tenant) {
+            return Ok(slot);
+        }
+        let path = tenant::path(&self.mecab_dic_path, tenant);
+        std::fs::create_dir_all(&path)?;
+        Ok(self.open(tenant, path))
+    }
+    fn open(&self, tenant: &str, path: PathBuf) -> Rc<TokenizerSlot> {
+        let slot = Rc::new(TokenizerSlot::new(Tokenizer::with_userdic_path(
+            &self.mecab_dic_path,
+            path,
+        )));
+        self.slots
+            .borrow_mut()
+            .insert(tenant.to_string(), slot.clone());
+        slot
+    }
+    /// Entries of every tenant's active build, opened here or not, by tenant.
+    pub fn userdic_entries(&self) -> Result<Vec<(String, usize)>> {
+        tenant::list(&self.mecab_dic_path)?
+            .into_iter()
+            .map(|tenant| {
+                let versions = Versions::new(tenant::path(&self.mecab_dic_path, &tenant));
+                let entries = match versions.current()? {
+                    Some(version) => versions.meta(version)?.entries,
+                    None => 0,
+                };
+                Ok((tenant, entries))
+            })
+            .collect()
+    }
+    /// Reloads the tenant's tokenizer, if it was opened.
+    pub async fn reload(&self, tenant: &str) -> Result<()> {
+        let slot = self.slots.borrow().get(tenant).cloned();
+        match slot {
+            Some(slot) => slot.reload().await,
+            None => Ok(()),
+        }
     }
 }
 
@@ -276,10 +878,206 @@ mod tests {
     #[test]
     fn tokenize_errorprune_word() {
         let tok = Tokenizer::new("");
-        let res = tok
+        let mut res = tok
             .tokenize("세종시 ㅋㅋ")
             .unwrap();
-        assert_eq!(format!("{:?}", res), "[Analytics { token: \"세종시\", tags: [\"NNP\"], symantic_group: Some(\"지명\"), has_support: Some(false), pronounce: Some(\"세종시\"), kind: Some(\"Compound\"), left_tag: None, right_tag: None, morphemes: Some([Morpheme { token: \"세종\", tag: \"NNP\" }, Morpheme { token: \"시\", tag: \"NNG\" }]) }, Analytics { token: \"ㅋㅋ\", tags: [\"UNKNOWN\"], symantic_group: None, has_support: None, pronounce: None, kind: None, left_tag: None, right_tag: None, morphemes: None }]");
+        for analytics in res.iter_mut() {
+            assert!(analytics.costs.take().is_some());
+        }
+        assert_eq!(format!("{:?}", res), "[Analytics { token: \"세종시\", lemma: \"세종시\", tags: [NNP], symantic_group: Some(\"지명\"), has_support: Some(false), pronounce: Some(\"세종시\"), kind: Some(\"Compound\"), left_tag: None, right_tag: None, morphemes: Some([Morpheme { token: \"세종\", tag: NNP, span: Span { start: 0, end: 2, byte_start: 0, byte_end: 6 } }, Morpheme { token: \"시\", tag: NNG, span: Span { start: 2, end: 3, byte_start: 6, byte_end: 9 } }]), unknown: false, span: Span { start: 0, end: 3, byte_start: 0, byte_end: 9 }, costs: None }, Analytics { token: \"ㅋㅋ\", lemma: \"ㅋㅋ\", tags: [UNKNOWN], symantic_group: None, has_support: None, pronounce: None, kind: None, left_tag: None, right_tag: None, morphemes: None, unknown: true, span: Span { start: 4, end: 6, byte_start: 10, byte_end: 16 }, costs: None }]");
     }
+    #[test]
+    fn tagger_args_name_both_dictionaries() {
+        assert_eq!(tagger_args(Path::new(""), Path::new(""), None).unwrap(), "");
+        let dir = std::env::temp_dir().join(format!("rest-mecab-args-{}", std::process::id()));
+        let versions = Versions::new(&dir);
+        let (version, build) = versions.create().unwrap();
+        assert_eq!(
+            tagger_args(&dir, &dir, Some(version)).unwrap(),
+            format!("-d {}", dir.display())
+        );
+        std::fs::write(build.join(dictionary::USER_DIC), b"").unwrap();
+        assert_eq!(
+            tagger_args(&dir, &dir, Some(version)).unwrap(),
+            format!(
+                "-d {} -u {}",
+                dir.display(),
+                build.join(dictionary::USER_DIC).display()
+            )
+        );
+        let err = tagger_args(Path::new("/opt/mecab dic"), &dir, None).unwrap_err();
+        assert!(err.to_string().contains("whitespace"));
+        let spaced = dir.join("a tenant");
+        let (version, build) = Versions::new(&spaced).create().unwrap();
+        std::fs::write(build.join(dictionary::USER_DIC), b"").unwrap();
+        assert!(tagger_args(&dir, &spaced, Some(version)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn analytics_from_feature() {
+        let analytics = Analytics::from_feature("a,b", "SL", true);
+        assert_eq!(analytics.token, "a,b");
+        assert_eq!(analytics.tags, vec![Tag::SL]);
+        assert_eq!(analytics.has_support, None);
+        assert!(analytics.morphemes.is_none());
+        assert!(analytics.unknown);
+        let analytics =
+            Analytics::from_feature("갔", "VV+EP,*,T,갔,Inflect,VV,EP,가/VV/*+았/EP/*", false);
+        assert_eq!(analytics.tags, vec![Tag::VV, Tag::EP]);
+        assert_eq!(analytics.left_tag, Some(Tag::VV));
+        assert_eq!(analytics.morphemes.as_ref().unwrap().len(), 2);
+        assert_eq!(analytics.lemma, "가다");
+        let analytics = Analytics::from_feature("걍", "MAG,*,T,걍,Inflect,*,*,그냥", false);
+        assert!(analytics.morphemes.is_none());
+        assert_eq!(analytics.lemma, "걍");
+        let analytics = Analytics::from_feature(
+            "공부했",
+            "NNG+XSV+EP,*,T,공부했,Inflect,NNG,EP,공부/NNG/*+하/XSV/*+았/EP/*",
+            false,
+        );
+        assert_eq!(analytics.lemma, "공부하다");
+        let analytics =
+            Analytics::from_feature("했", "XSV+EP,*,T,했,Inflect,XSV,EP,하/XSV/*+았", false);
+        assert!(analytics.morphemes.is_none());
+        assert_eq!(analytics.tags, vec![Tag::XSV, Tag::EP]);
+        assert_eq!(analytics.lemma, "했다");
+        assert_eq!(
+            Analytics::from_feature("", "", false).tags,
+            vec![Tag::UNKNOWN]
+        );
+        assert!(Analytics::parse("no tab").is_err());
+    }
+    #[test]
+    fn tokenize_costs() {
+        let tok = Tokenizer::new("");
+        let res = tok.tokenize("세종시에 갔다").unwrap();
+        let mut path = 0;
+        for analytics in res.iter() {
+            let costs = analytics.costs.unwrap();
+            path += costs.word + costs.connection;
+            assert_eq!(costs.path, path);
+        }
+        let candidates = tok
+            .tokenize_nbest("세종시에 갔다", 1, &NormalizeConfig::default())
+            .unwrap();
+        assert_eq!(
+            candidates[0]
+                .analytics
+                .iter()
+                .map(|analytics| analytics.costs)
+                .collect::<Vec<_>>(),
+            res.iter()
+                .map(|analytics| analytics.costs)
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn tokenize_nbest() {
+        let tok = Tokenizer::new("");
+        let best = tok.tokenize("세종시 ㅋㅋ").unwrap();
+        let candidates = tok
+            .tokenize_nbest("세종시 ㅋㅋ", 3, &NormalizeConfig::default())
+            .unwrap();
+        assert!(!candidates.is_empty() && candidates.len() <= 3);
+        assert_eq!(
+            candidates[0]
+                .analytics
+                .iter()
+                .map(|analytics| analytics.token.as_str())
+                .collect::<Vec<_>>(),
+            best.iter()
+                .map(|analytics| analytics.token.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(candidates
+            .windows(2)
+            .all(|pair| pair[0].cost <= pair[1].cost));
 
+        let lemmas = |analytics: &[Analytics]| {
+            analytics
+                .iter()
+                .map(|analytics| analytics.lemma.clone())
+                .collect::<Vec<_>>()
+        };
+        let best = tok.tokenize("공부했다").unwrap();
+        let candidates = tok
+            .tokenize_nbest("공부했다", 3, &NormalizeConfig::default())
+            .unwrap();
+        assert_eq!(lemmas(&candidates[0].analytics), lemmas(&best));
+        assert!(lemmas(&best).contains(&"공부하다".to_string()));
+        for candidate in candidates.iter() {
+            assert!(lemmas(&candidate.analytics)
+                .iter()
+                .all(|lemma| !lemma.contains("공부공부")));
+        }
+    }
+    #[test]
+    fn tokenize_lemma() {
+        let tok = Tokenizer::new("");
+        let res = tok.tokenize("밥을 먹었다 갔다 공부했다").unwrap();
+        let lemmas = res
+            .iter()
+            .map(|analytics| analytics.lemma.as_str())
+            .collect::<Vec<_>>();
+        assert!(lemmas.contains(&"먹다"));
+        assert!(lemmas.contains(&"가다"));
+        assert!(lemmas.contains(&"공부하다"));
+        assert_eq!(lemmas[0], "밥");
+    }
+    #[test]
+    fn derived_stem_lemma() {
+        let mut analytics = vec![
+            Analytics::from_feature("공부", "NNG,*,F,공부,*,*,*,*", false),
+            Analytics::from_feature("했", "XSV+EP,*,T,했,Inflect,XSV,EP,하/XSV/*+았/EP/*", false),
+            Analytics::from_feature("공부", "NNG,*,F,공부,*,*,*,*", false),
+            Analytics::from_feature("했", "XSV+EP,*,T,했,Inflect,XSV,EP,하/XSV/*+았/EP/*", false),
+        ];
+        let normalized = NormalizedText::new("공부했 공부 했");
+        assign_spans(&normalized, &mut analytics);
+        join_derived_stems(&mut analytics);
+        let lemmas = analytics
+            .iter()
+            .map(|analytics| analytics.lemma.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lemmas, vec!["공부", "공부하다", "공부", "하다"]);
+    }
+    #[test]
+    fn tag_filter_and_expand() {
+        let tok = Tokenizer::new("");
+        let res = tok.tokenize("세종시 ㅋㅋ").unwrap();
+        let filter = TagFilter::parse(Some("NN*"), None);
+        let nouns = res
+            .into_iter()
+            .filter(|analytics| filter.matches(analytics))
+            .flat_map(Analytics::expand)
+            .collect::<Vec<_>>();
+        assert_eq!(nouns.len(), 2);
+        assert_eq!(nouns[0].token, "세종");
+        assert_eq!(nouns[0].tags, vec![Tag::NNP]);
+        assert_eq!(nouns[1].token, "시");
+        let filter = TagFilter::parse(Some("NN*, V*"), Some("NNB"));
+        assert!(filter.matches_tag("NNG"));
+        assert!(filter.matches_tag("VV"));
+        assert!(!filter.matches_tag("NNB"));
+        assert!(!filter.matches_tag("SF"));
+    }
+    #[test]
+    fn tokenize_spans_point_into_unnormalized_input() {
+        let tok = Tokenizer::new("");
+        let q = "  세종시    ㅋㅋㅋㅋㅋ";
+        let res = tok.tokenize(q).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(
+            res[0].span,
+            Span {
+                start: 2,
+                end: 5,
+                byte_start: 2,
+                byte_end: 11
+            }
+        );
+        assert_eq!(&q[res[0].span.byte_start..res[0].span.byte_end], "세종시");
+        assert_eq!((res[1].span.start, res[1].span.end), (9, 12));
+        assert_eq!(&q[res[1].span.byte_start..res[1].span.byte_end], "ㅋㅋㅋ");
+    }
 }
@@ -0,0 +1,631 @@
+use crate::tag::Tag;
+use crate::tokenizer::has_support;
+use crate::versions::Versions;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Learned nouns pushed by the userdic server live in this file under `user-dic/`.
+pub const LEARNED: &str = "rest-mecab";
+/// Hand-edited entries are written to this file under `user-dic/` and win over everything.
+pub const MANUAL: &str = "manual";
+/// Hand-edited entries, kept as one JSON array under the MeCab dic path.
+const MANUAL_ENTRIES: &str = "user-dic.manual.json";
+/// The learned nouns as last pushed, before words taken by other dictionaries are dropped.
+const LEARNED_WORDS: &str = "user-dic.learned.json";
+/// Outcome of the last sync of the learned nouns.
+const LAST_SYNC: &str = "user-dic.sync.json";
+/// Definitions of the named dictionaries, one JSON file each, under the MeCab dic path.
+const DEFINITIONS_DIR: &str = "user-dic.d";
+/// Files the CSVs are written from besides the definitions, under the MeCab dic path.
+const SOURCE_FILES: &[&str] = &[MANUAL_ENTRIES, LEARNED_WORDS];
+
+fn default_pos() -> Tag {
+    Tag::NNP
+}
+
+/// One word of a user dictionary: a line of the MeCab CSV. Left and right context ids are
+/// left empty for the dictionary compiler to fill in, so is the cost unless one is given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(default)]
+    pub surface: String,
+    #[serde(default = "default_pos")]
+    pub pos: Tag,
+    /// Defaults to the surface.
+    #[serde(default)]
+    pub reading: Option<String>,
+    #[serde(default)]
+    pub semantic_group: Option<String>,
+    #[serde(default)]
+    pub cost: Option<i16>,
+}
+
+impl Entry {
+    pub fn new(surface: &str, pos: &Tag, cost: Option<i16>) -> Self {
+        Self {
+            surface: surface.to_string(),
+            pos: pos.clone(),
+            reading: None,
+            semantic_group: None,
+            cost,
+        }
+    }
+    /// Reads back a line written by `csv`, or any line of a mecab-ko-dic CSV.
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields = line.split(',').collect::<Vec<_>>();
+        if fields.len() < 8 || fields[0].is_empty() {
+            return Err(Error::msg(format!("not a dictionary line: {}", line)));
+        }
+        let field = |i: usize| Some(fields[i]).filter(|f| !f.is_empty() && *f != "*");
+        Ok(Self {
+            surface: fields[0].to_string(),
+            pos: Tag::from(fields[4]),
+            reading: field(7)
+                .filter(|reading| *reading != fields[0])
+                .map(|reading| reading.to_string()),
+            semantic_group: field(5).map(|group| group.to_string()),
+            cost: match field(3) {
+                Some(cost) => Some(cost.parse()?),
+                None => None,
+            },
+        })
+    }
+    /// Fails for an entry the dictionary compiler would fail on: one with a POS outside the
+    /// tagset, or one that is not a valid CSV line.
+    pub fn check(&self) -> Result<()> {
+        if !self.pos.is_known() {
+            return Err(Error::msg(format!("unknown POS: {}", self.pos)));
+        }
+        self.csv().map(|_| ())
+    }
+    pub fn csv(&self) -> Result<String> {
+        let last = self
+            .surface
+            .chars()
+            .last()
+            .ok_or_else(|| Error::msg(format!("{} length 0", self.pos)))?;
+        let fields = [
+            Some(self.surface.as_str()),
+            self.reading.as_deref(),
+            self.semantic_group.as_deref(),
+        ];
+        if fields
+            .iter()
+            .flatten()
+            .any(|field| field.contains(&[',', '\n', '\r'][..]))
+        {
+            return Err(Error::msg(format!(
+                "entry fields must not contain commas or newlines: {}",
+                self.surface
+            )));
+        }
+        Ok(format!(
+            "{},,,{},{},{},{},{},*,*,*,*",
+            self.surface,
+            self.cost.map(|c| c.to_string()).unwrap_or_default(),
+            self.pos,
+            self.semantic_group.as_deref().unwrap_or("*"),
+            if has_support(last) { "T" } else { "F" },
+            self.reading.as_deref().unwrap_or(&self.surface)
+        ))
+    }
+}
+
+/// How the last sync of the learned nouns from the userdic server went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+    pub source: String,
+    pub ok: bool,
+    /// Set when the sync built a new version, a sync that found nothing new builds none.
+    pub changed: bool,
+    /// Learned nouns dropped for not being valid.
+    #[serde(default)]
+    pub rejected: usize,
+    pub error: Option<String>,
+}
+
+/// A named user dictionary. Every word in it shares one POS and cost; when a word appears in
+/// several dictionaries the one with the highest priority wins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserDic {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_pos")]
+    pub pos: Tag,
+    #[serde(default)]
+    pub cost: Option<i16>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub words: Vec<String>,
+}
+
+impl UserDic {
+    /// Words that can't be written as a dictionary line, such as ones with a `,` or a newline.
+    pub fn invalid_words(&self) -> Vec<&str> {
+        self.words
+            .iter()
+            .filter(|w| !w.is_empty() && Entry::new(w, &self.pos, self.cost).csv().is_err())
+            .map(|w| w.as_str())
+            .collect()
+    }
+    fn csv(&self, taken: &mut HashSet<String>) -> String {
+        self.words
+            .iter()
+            .filter(|w| taken.insert(w.to_string()))
+            .filter_map(|w| Entry::new(w, &self.pos, self.cost).csv().ok())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Copies the files with `extension` in `from` to `to`.
+fn copy_files(from: &Path, to: &Path, extension: &str) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    if !from.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some(extension) {
+            if let Some(name) = path.file_name() {
+                std::fs::copy(&path, to.join(name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the files with `extension` in `dir`.
+fn remove_files(dir: &Path, extension: &str) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some(extension) {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Names end up as file names, so only ASCII letters, digits, `-` and `_` are allowed.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != LEARNED
+        && name != MANUAL
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Named dictionaries and manual entries stored next to the MeCab dictionary they are compiled into.
+pub struct UserDics {
+    mecab_dic_path: PathBuf,
+}
+
+impl UserDics {
+    pub fn new<P: AsRef<Path>>(mecab_dic_path: P) -> Self {
+        Self {
+            mecab_dic_path: mecab_dic_path.as_ref().to_path_buf(),
+        }
+    }
+    fn definition_path(&self, name: &str) -> PathBuf {
+        self.mecab_dic_path
+            .join(DEFINITIONS_DIR)
+            .join(format!("{}.json", name))
+    }
+    fn csv_path(&self, name: &str) -> PathBuf {
+        self.mecab_dic_path
+            .join("user-dic")
+            .join(format!("{}.csv", name))
+    }
+    /// All named dictionaries, highest priority first.
+    pub fn list(&self) -> Result<Vec<UserDic>> {
+        let dir = self.mecab_dic_path.join(DEFINITIONS_DIR);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut dics = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let mut dic: UserDic = serde_json::from_slice(&std::fs::read(&path)?)?;
+            if let Some(name) = path.file_stem() {
+                dic.name = name.to_string_lossy().to_string();
+            }
+            dics.push(dic);
+        }
+        dics.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.name.cmp(&b.name)));
+        Ok(dics)
+    }
+    pub fn get(&self, name: &str) -> Result<Option<UserDic>> {
+        Ok(self.list()?.into_iter().find(|dic| dic.name == name))
+    }
+    /// Creates or replaces a dictionary. Call `write_csv` afterwards to compile it in.
+    pub fn put(&self, mut dic: UserDic) -> Result<()> {
+        if !is_valid_name(&dic.name) {
+            return Err(Error::msg(format!("invalid dictionary name: {}", dic.name)));
+        }
+        if !dic.pos.is_known() {
+            return Err(Error::msg(format!("unknown POS: {}", dic.pos)));
+        }
+        let invalid = dic.invalid_words();
+        if !invalid.is_empty() {
+            return Err(Error::msg(format!("invalid words: {:?}", invalid)));
+        }
+        dic.words.retain(|w| !w.is_empty());
+        std::fs::create_dir_all(self.mecab_dic_path.join(DEFINITIONS_DIR))?;
+        std::fs::write(
+            self.definition_path(&dic.name),
+            serde_json::to_vec_pretty(&dic)?,
+        )?;
+        Ok(())
+    }
+    /// Returns false when there was no such dictionary.
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        if !is_valid_name(name) || !self.definition_path(name).exists() {
+            return Ok(false);
+        }
+        std::fs::remove_file(self.definition_path(name))?;
+        if self.csv_path(name).exists() {
+            std::fs::remove_file(self.csv_path(name))?;
+        }
+        Ok(true)
+    }
+    /// Manual entries, sorted by surface.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let path = self.mecab_dic_path.join(MANUAL_ENTRIES);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+    pub fn entry(&self, surface: &str) -> Result<Option<Entry>> {
+        Ok(self.entries()?.into_iter().find(|e| e.surface == surface))
+    }
+    fn write_entries(&self, entries: &[Entry]) -> Result<()> {
+        std::fs::create_dir_all(&self.mecab_dic_path)?;
+        std::fs::write(
+            self.mecab_dic_path.join(MANUAL_ENTRIES),
+            serde_json::to_vec_pretty(entries)?,
+        )?;
+        Ok(())
+    }
+    /// Adds an entry or replaces the one with the same surface. Returns true when it is new.
+    pub fn put_entry(&self, entry: Entry) -> Result<bool> {
+        entry.check()?;
+        let mut entries = self.entries()?;
+        let created = match entries.iter_mut().find(|e| e.surface == entry.surface) {
+            Some(e) => {
+                *e = entry;
+                false
+            }
+            None => {
+                entries.push(entry);
+                true
+            }
+        };
+        entries.sort_by(|a, b| a.surface.cmp(&b.surface));
+        self.write_entries(&entries)?;
+        Ok(created)
+    }
+    /// Returns false when there was no such entry.
+    pub fn delete_entry(&self, surface: &str) -> Result<bool> {
+        let mut entries = self.entries()?;
+        let len = entries.len();
+        entries.retain(|e| e.surface != surface);
+        if entries.len() == len {
+            return Ok(false);
+        }
+        self.write_entries(&entries)?;
+        Ok(true)
+    }
+    /// Words of the learned dictionary as last pushed. Dictionaries written before this list
+    /// was kept are read back from their CSV.
+    pub fn learned_words(&self) -> Result<Vec<String>> {
+        let path = self.mecab_dic_path.join(LEARNED_WORDS);
+        if path.exists() {
+            return Ok(serde_json::from_slice(&std::fs::read(path)?)?);
+        }
+        let path = self.csv_path(LEARNED);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .filter_map(|line| line.split(',').next())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_string())
+            .collect())
+    }
+    /// Where the learned nouns of the active build are in the userdic server's change log, if
+    /// known. It is recorded with the build, so the two always match.
+    pub fn learned_seq(&self) -> Result<Option<u64>> {
+        let versions = Versions::new(&self.mecab_dic_path);
+        match versions.current()? {
+            Some(version) => Ok(versions.meta(version)?.learned_seq),
+            None => Ok(None),
+        }
+    }
+    /// Copies what the CSVs are written from, the named dictionaries, the manual entries and
+    /// the learned nouns, into `target`.
+    pub fn save_sources(&self, target: &Path) -> Result<()> {
+        copy_files(
+            &self.mecab_dic_path.join(DEFINITIONS_DIR),
+            &target.join(DEFINITIONS_DIR),
+            "json",
+        )?;
+        for name in SOURCE_FILES {
+            let path = self.mecab_dic_path.join(name);
+            if path.exists() {
+                std::fs::copy(path, target.join(name))?;
+            }
+        }
+        Ok(())
+    }
+    /// Replaces the sources with the ones `save_sources` put in `sources`, and the CSVs with
+    /// the ones in `csv_dir` that were written from them.
+    pub fn restore_sources(&self, sources: &Path, csv_dir: &Path) -> Result<()> {
+        let definitions = self.mecab_dic_path.join(DEFINITIONS_DIR);
+        remove_files(&definitions, "json")?;
+        copy_files(&sources.join(DEFINITIONS_DIR), &definitions, "json")?;
+        for name in SOURCE_FILES {
+            let (saved, path) = (sources.join(name), self.mecab_dic_path.join(name));
+            if saved.exists() {
+                std::fs::copy(saved, path)?;
+            } else if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let user_dic = self.mecab_dic_path.join("user-dic");
+        remove_files(&user_dic, "csv")?;
+        copy_files(csv_dir, &user_dic, "csv")
+    }
+    pub fn last_sync(&self) -> Result<Option<SyncStatus>> {
+        let path = self.mecab_dic_path.join(LAST_SYNC);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+    pub fn set_last_sync(&self, status: &SyncStatus) -> Result<()> {
+        std::fs::create_dir_all(&self.mecab_dic_path)?;
+        std::fs::write(
+            self.mecab_dic_path.join(LAST_SYNC),
+            serde_json::to_vec_pretty(status)?,
+        )?;
+        Ok(())
+    }
+    /// Writes the manual entries, a CSV per named dictionary and the learned nouns under
+    /// `user-dic/`. A word is only written once: manual entries first, then named dictionaries
+    /// by priority, then learned nouns. Passing `None` keeps the learned nouns already on disk.
+    pub fn write_csv(&self, learned: Option<Vec<String>>) -> Result<()> {
+        std::fs::create_dir_all(self.mecab_dic_path.join("user-dic"))?;
+        let learned = match learned {
+            Some(learned) => {
+                std::fs::write(
+                    self.mecab_dic_path.join(LEARNED_WORDS),
+                    serde_json::to_vec(&learned)?,
+                )?;
+                learned
+            }
+            None => self.learned_words()?,
+        };
+        let mut taken = HashSet::new();
+        let manual = self
+            .entries()?
+            .into_iter()
+            .filter(|e| taken.insert(e.surface.clone()))
+            .filter_map(|e| e.csv().ok())
+            .collect::<Vec<_>>();
+        std::fs::write(self.csv_path(MANUAL), manual.join("\n"))?;
+        for dic in self.list()? {
+            std::fs::write(self.csv_path(&dic.name), dic.csv(&mut taken))?;
+        }
+        let learned = UserDic {
+            name: LEARNED.to_string(),
+            pos: default_pos(),
+            cost: None,
+            priority: i32::MIN,
+            words: learned,
+        };
+        std::fs::write(self.csv_path(LEARNED), learned.csv(&mut taken))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_format() {
+        let csv = |surface: &str, pos: &Tag, cost| Entry::new(surface, pos, cost).csv().unwrap();
+        assert_eq!(
+            csv("세종시", &Tag::NNP, None),
+            "세종시,,,,NNP,*,F,세종시,*,*,*,*"
+        );
+        assert_eq!(
+            csv("감스트", &Tag::NNG, Some(-100)),
+            "감스트,,,-100,NNG,*,F,감스트,*,*,*,*"
+        );
+        assert_eq!(csv("뤣", &Tag::NNP, None), "뤣,,,,NNP,*,T,뤣,*,*,*,*");
+        let entry = Entry {
+            surface: "BTS".to_string(),
+            pos: Tag::NNP,
+            reading: Some("비티에스".to_string()),
+            semantic_group: Some("인명".to_string()),
+            cost: Some(0),
+        };
+        assert_eq!(entry.csv().unwrap(), "BTS,,,0,NNP,인명,F,비티에스,*,*,*,*");
+        assert_eq!(Entry::parse(&entry.csv().unwrap()).unwrap(), entry);
+        assert_eq!(
+            Entry::parse("세종시,,,,NNP,*,F,세종시,*,*,*,*").unwrap(),
+            Entry::new("세종시", &Tag::NNP, None)
+        );
+        assert!(Entry::parse("세종시,,,").is_err());
+        assert!(Entry::new("", &Tag::NNP, None).csv().is_err());
+        assert!(Entry::new("a,b", &Tag::NNP, None).csv().is_err());
+    }
+
+    #[test]
+    fn manual_entries_win() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-manual-{}", std::process::id()));
+        let dics = UserDics::new(&dir);
+        assert!(dics
+            .put_entry(Entry::new("감스트", &Tag::NNP, None))
+            .unwrap());
+        assert!(!dics
+            .put_entry(Entry::new("감스트", &Tag::NNG, Some(-10)))
+            .unwrap());
+        assert!(dics.put_entry(Entry::new("a\nb", &Tag::NNG, None)).is_err());
+        assert!(dics
+            .put_entry(Entry::new("톩톩", &Tag::from("NNQ"), None))
+            .is_err());
+        assert_eq!(dics.entries().unwrap().len(), 1);
+        assert_eq!(dics.entry("감스트").unwrap().unwrap().pos, Tag::NNG);
+        dics.put(UserDic {
+            name: "slang".to_string(),
+            pos: Tag::NNP,
+            cost: None,
+            priority: 100,
+            words: vec!["감스트".to_string()],
+        })
+        .unwrap();
+        dics.write_csv(Some(vec!["감스트".to_string()])).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dics.csv_path(name)).unwrap();
+        assert_eq!(read(MANUAL), "감스트,,,-10,NNG,*,F,감스트,*,*,*,*");
+        assert_eq!(read("slang"), "");
+        assert_eq!(read(LEARNED), "");
+
+        assert!(dics.delete_entry("감스트").unwrap());
+        assert!(!dics.delete_entry("감스트").unwrap());
+        dics.write_csv(None).unwrap();
+        assert_eq!(read(MANUAL), "");
+        assert_eq!(read("slang").lines().count(), 1);
+        dics.delete("slang").unwrap();
+        dics.write_csv(None).unwrap();
+        assert_eq!(read(LEARNED).lines().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn named_dictionaries_by_priority() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-userdic-{}", std::process::id()));
+        let dics = UserDics::new(&dir);
+        dics.put(UserDic {
+            name: "brand".to_string(),
+            pos: Tag::NNP,
+            cost: Some(-1000),
+            priority: 10,
+            words: vec!["감스트".to_string(), "뤣쉙".to_string()],
+        })
+        .unwrap();
+        dics.put(UserDic {
+            name: "slang".to_string(),
+            pos: Tag::NNG,
+            cost: None,
+            priority: 0,
+            words: vec!["감스트".to_string(), "톩톩".to_string()],
+        })
+        .unwrap();
+        assert!(dics
+            .put(UserDic {
+                name: "../escape".to_string(),
+                pos: Tag::NNG,
+                cost: None,
+                priority: 0,
+                words: vec![],
+            })
+            .is_err());
+        assert!(dics
+            .put(UserDic {
+                name: "unknown".to_string(),
+                pos: Tag::from("NNQ"),
+                cost: None,
+                priority: 0,
+                words: vec![],
+            })
+            .is_err());
+        let bad = UserDic {
+            name: "bad".to_string(),
+            pos: Tag::NNG,
+            cost: None,
+            priority: 0,
+            words: vec!["좋은".to_string(), "a,b".to_string(), "c\nd".to_string()],
+        };
+        assert_eq!(bad.invalid_words(), vec!["a,b", "c\nd"]);
+        assert!(dics.put(bad).is_err());
+        dics.write_csv(Some(vec!["톩톩".to_string(), "쒧".to_string()]))
+            .unwrap();
+
+        let names = dics
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|dic| dic.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["brand", "slang"]);
+        let read = |name: &str| std::fs::read_to_string(dics.csv_path(name)).unwrap();
+        assert_eq!(read("brand").lines().count(), 2);
+        assert_eq!(read("slang"), "톩톩,,,,NNG,*,T,톩톩,*,*,*,*");
+        assert_eq!(read(LEARNED), "쒧,,,,NNP,*,T,쒧,*,*,*,*");
+
+        assert!(dics.delete("brand").unwrap());
+        assert!(!dics.delete("brand").unwrap());
+        dics.write_csv(None).unwrap();
+        assert_eq!(read("slang").lines().count(), 2);
+        assert_eq!(dics.learned_words().unwrap(), vec!["톩톩", "쒧"]);
+        dics.delete("slang").unwrap();
+        dics.write_csv(None).unwrap();
+        assert_eq!(read(LEARNED).lines().count(), 2);
+        assert_eq!(dics.learned_seq().unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_and_restore_sources() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-sources-{}", std::process::id()));
+        let (sources, csv_dir) = (dir.join("saved"), dir.join("saved-csv"));
+        let dics = UserDics::new(&dir);
+        let slang = |words: &[&str]| UserDic {
+            name: "slang".to_string(),
+            pos: Tag::NNG,
+            cost: None,
+            priority: 0,
+            words: words.iter().map(|w| w.to_string()).collect(),
+        };
+        dics.put(slang(&["톩톩"])).unwrap();
+        dics.put_entry(Entry::new("감스트", &Tag::NNP, None))
+            .unwrap();
+        dics.write_csv(Some(vec!["쒧".to_string()])).unwrap();
+        dics.save_sources(&sources).unwrap();
+        copy_files(&dir.join("user-dic"), &csv_dir, "csv").unwrap();
+
+        dics.put(slang(&["뤣쉙"])).unwrap();
+        dics.put(UserDic {
+            name: "brand".to_string(),
+            ..slang(&["뷁"])
+        })
+        .unwrap();
+        dics.delete_entry("감스트").unwrap();
+        dics.write_csv(Some(vec![])).unwrap();
+
+        dics.restore_sources(&sources, &csv_dir).unwrap();
+        assert_eq!(dics.list().unwrap(), vec![slang(&["톩톩"])]);
+        assert!(dics.entry("감스트").unwrap().is_some());
+        assert_eq!(dics.learned_words().unwrap(), vec!["쒧"]);
+        assert!(!dics.csv_path("brand").exists());
+        let read = |name: &str| std::fs::read_to_string(dics.csv_path(name)).unwrap();
+        assert_eq!(read("slang"), "톩톩,,,,NNG,*,T,톩톩,*,*,*,*");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
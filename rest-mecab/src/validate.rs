@@ -0,0 +1,100 @@
+//! The gate learned nouns from the userdic server pass before they are compiled in. A sync
+//! whose nouns fail it is refused, and the tokenizer keeps serving its current dictionary.
+use anyhow::{Error, Result};
+
+/// Longest noun taken, in chars.
+pub const MAX_NOUN_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest response taken from the userdic server, in bytes.
+    pub max_bytes: usize,
+    /// Most learned nouns a dictionary takes.
+    pub max_entries: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024 * 1024,
+            max_entries: 5_000_000,
+        }
+    }
+}
+
+fn is_hangul_syllable(c: char) -> bool {
+    ('\u{AC00}'..='\u{D7A3}').contains(&c)
+}
+
+/// Hangul syllables, possibly mixed with ASCII letters and digits as in 갤럭시S20. Anything
+/// else, commas and newlines included, would be noise in the dictionary or break its CSV.
+pub fn is_valid_noun(noun: &str) -> bool {
+    let chars = noun.chars().count();
+    chars > 0
+        && chars <= MAX_NOUN_CHARS
+        && noun.chars().any(is_hangul_syllable)
+        && noun
+            .chars()
+            .all(|c| is_hangul_syllable(c) || c.is_ascii_alphanumeric())
+}
+
+/// Drops the nouns that are not valid and returns the rest with how many were dropped. Fails
+/// when more than `max_entries` remain, or when most of the nouns were dropped, which points
+/// at a broken upstream rather than a few bad words.
+pub fn check_nouns(nouns: Vec<String>, limits: &Limits) -> Result<(Vec<String>, usize)> {
+    let received = nouns.len();
+    let nouns = nouns
+        .into_iter()
+        .filter(|noun| is_valid_noun(noun))
+        .collect::<Vec<_>>();
+    let rejected = received - nouns.len();
+    if rejected * 2 > received {
+        return Err(Error::msg(format!(
+            "{} of {} learned nouns are not valid",
+            rejected, received
+        )));
+    }
+    if nouns.len() > limits.max_entries {
+        return Err(Error::msg(format!(
+            "{} learned nouns, more than the limit of {}",
+            nouns.len(),
+            limits.max_entries
+        )));
+    }
+    Ok((nouns, rejected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nouns_gate() {
+        assert!(is_valid_noun("뤣쉙퀡"));
+        assert!(is_valid_noun("갤럭시S20"));
+        for noun in &[
+            "",
+            "BTS",
+            "감,스트",
+            "감스트\n",
+            "ㅋㅋㅋ",
+            "감스트!",
+            "감 스트",
+        ] {
+            assert!(!is_valid_noun(noun), "{:?}", noun);
+        }
+        assert!(!is_valid_noun(&"뤣".repeat(MAX_NOUN_CHARS + 1)));
+
+        let nouns = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let limits = Limits {
+            max_bytes: 1024,
+            max_entries: 2,
+        };
+        let (checked, rejected) = check_nouns(nouns(&["감스트", "톩톩", "a,b"]), &limits).unwrap();
+        assert_eq!(checked, vec!["감스트", "톩톩"]);
+        assert_eq!(rejected, 1);
+        assert!(check_nouns(nouns(&["감스트", "a,b", "ㅋㅋ"]), &limits).is_err());
+        assert!(check_nouns(nouns(&["감스트", "톩톩", "뤣쉙퀡"]), &limits).is_err());
+        assert_eq!(check_nouns(vec![], &limits).unwrap(), (vec![], 0));
+    }
+}
@@ -0,0 +1,335 @@
+//! Every user dictionary build goes into a numbered directory of its own under `versions/`,
+//! and a `current` file names the one taggers load. A build is only pointed to once it is
+//! complete and checked, so a tagger never opens a half-written dictionary. Each build keeps
+//! the CSVs it was compiled from, the sources they were written from and a `meta.json`, so
+//! builds can be compared and an earlier one restored.
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Build directories, under the MeCab dic path.
+const VERSIONS_DIR: &str = "versions";
+/// Holds the number of the active build, under `versions/`.
+const CURRENT: &str = "current";
+/// The build's metadata, in each build directory.
+const META: &str = "meta.json";
+/// The CSVs the build was compiled from, in each build directory.
+const CSV_DIR: &str = "user-dic";
+/// The named dictionaries, manual entries and learned nouns the CSVs were written from, in
+/// each build directory.
+const SOURCES_DIR: &str = "sources";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    pub version: u64,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub entries: usize,
+    /// Where the learned nouns came from, for builds made by a sync.
+    pub source: Option<String>,
+    /// See [`Versions::hash`]. Builds from before it was recorded have none.
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Sequence of the last change in the userdic server's log the learned nouns reflect,
+    /// when known.
+    #[serde(default)]
+    pub learned_seq: Option<u64>,
+}
+
+/// Surfaces whose CSV lines differ between two builds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diff {
+    pub from: u64,
+    pub to: u64,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+pub struct Versions {
+    root: PathBuf,
+}
+
+impl Versions {
+    pub fn new<P: AsRef<Path>>(mecab_dic_path: P) -> Self {
+        Self {
+            root: mecab_dic_path.as_ref().join(VERSIONS_DIR),
+        }
+    }
+    pub fn dir(&self, version: u64) -> PathBuf {
+        self.root.join(version.to_string())
+    }
+    /// The CSVs `version` was compiled from.
+    pub fn csv_dir(&self, version: u64) -> PathBuf {
+        self.dir(version).join(CSV_DIR)
+    }
+    /// The sources of `version`'s CSVs. Builds from before they were kept have none.
+    pub fn sources_dir(&self, version: u64) -> PathBuf {
+        self.dir(version).join(SOURCES_DIR)
+    }
+    pub fn exists(&self, version: u64) -> bool {
+        self.dir(version).is_dir()
+    }
+    /// The active build, if one was activated yet.
+    pub fn current(&self) -> Result<Option<u64>> {
+        match std::fs::read_to_string(self.root.join(CURRENT)) {
+            Ok(version) => Ok(Some(version.trim().parse()?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+    /// All builds, oldest first.
+    pub fn list(&self) -> Result<Vec<u64>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut versions = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                if let Some(version) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+                    versions.push(version);
+                }
+            }
+        }
+        versions.sort_unstable();
+        Ok(versions)
+    }
+    /// Makes an empty directory for a new build, numbered one past the newest build. Callers
+    /// serialize builds, numbers are not reserved otherwise.
+    pub fn create(&self) -> Result<(u64, PathBuf)> {
+        std::fs::create_dir_all(&self.root)?;
+        let version = self.list()?.last().map_or(1, |last| last + 1);
+        let dir = self.dir(version);
+        std::fs::create_dir(&dir)?;
+        Ok((version, dir))
+    }
+    /// Copies the CSVs a build is compiled from into its directory.
+    pub fn snapshot<P: AsRef<Path>>(&self, version: u64, csv_dir: P) -> Result<()> {
+        let target = self.csv_dir(version);
+        std::fs::create_dir_all(&target)?;
+        for entry in std::fs::read_dir(csv_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("csv") {
+                if let Some(name) = path.file_name() {
+                    std::fs::copy(&path, target.join(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn write_meta(&self, meta: &Meta) -> Result<()> {
+        std::fs::write(
+            self.dir(meta.version).join(META),
+            serde_json::to_vec_pretty(meta)?,
+        )?;
+        Ok(())
+    }
+    pub fn meta(&self, version: u64) -> Result<Meta> {
+        let path = self.dir(version).join(META);
+        let bytes = std::fs::read(&path)
+            .map_err(|err| Error::msg(format!("{}: {}", path.display(), err)))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+    /// Metadata of all finished builds, oldest first. A build still being compiled has none.
+    pub fn metas(&self) -> Result<Vec<Meta>> {
+        self.list()?
+            .into_iter()
+            .filter(|version| self.dir(*version).join(META).exists())
+            .map(|version| self.meta(version))
+            .collect()
+    }
+    /// The CSVs of a build, by file name.
+    fn csv_files(&self, version: u64) -> Result<Vec<PathBuf>> {
+        let csv_dir = self.csv_dir(version);
+        if !csv_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut paths = std::fs::read_dir(csv_dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        paths.sort();
+        Ok(paths)
+    }
+    /// SHA-1 of the CSVs a build was compiled from, with their names. Builds of the same
+    /// entries hash the same, whichever pod compiled them.
+    pub fn hash(&self, version: u64) -> Result<String> {
+        let mut sha1 = sha1::Sha1::new();
+        for path in self.csv_files(version)? {
+            if let Some(name) = path.file_name() {
+                sha1.update(name.to_string_lossy().as_bytes());
+            }
+            sha1.update(&[0]);
+            sha1.update(&std::fs::read(&path)?);
+            sha1.update(&[0]);
+        }
+        Ok(sha1.digest().to_string())
+    }
+    /// Every CSV line of a build, file by file.
+    pub fn entries(&self, version: u64) -> Result<Vec<String>> {
+        let mut entries = Vec::new();
+        for path in self.csv_files(version)? {
+            entries.extend(
+                std::fs::read_to_string(path)?
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.to_string()),
+            );
+        }
+        Ok(entries)
+    }
+    /// The CSV lines of a build, by surface.
+    fn lines(&self, version: u64) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut lines = BTreeMap::<String, Vec<String>>::new();
+        for path in self.csv_files(version)? {
+            for line in std::fs::read_to_string(path)?.lines() {
+                if let Some(surface) = line.split(',').next().filter(|s| !s.is_empty()) {
+                    lines
+                        .entry(surface.to_string())
+                        .or_default()
+                        .push(line.to_string());
+                }
+            }
+        }
+        for surface_lines in lines.values_mut() {
+            surface_lines.sort();
+        }
+        Ok(lines)
+    }
+    pub fn diff(&self, from: u64, to: u64) -> Result<Diff> {
+        for version in [from, to].iter() {
+            if !self.exists(*version) {
+                return Err(Error::msg(format!("no dictionary version {}", version)));
+            }
+        }
+        let (old, new) = (self.lines(from)?, self.lines(to)?);
+        let mut diff = Diff {
+            from,
+            to,
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (surface, lines) in new.iter() {
+            match old.get(surface) {
+                None => diff.added.push(surface.clone()),
+                Some(old_lines) if old_lines != lines => diff.changed.push(surface.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .into_keys()
+            .filter(|surface| !new.contains_key(surface))
+            .collect();
+        Ok(diff)
+    }
+    /// Points `current` at `version`. The file is replaced by a rename, so readers see either
+    /// the old or the new number.
+    pub fn activate(&self, version: u64) -> Result<()> {
+        if !self.exists(version) {
+            return Err(Error::msg(format!("no dictionary version {}", version)));
+        }
+        let tmp_path = self.root.join(format!("{}.tmp", CURRENT));
+        std::fs::write(&tmp_path, version.to_string())?;
+        std::fs::rename(tmp_path, self.root.join(CURRENT))?;
+        Ok(())
+    }
+    pub fn remove(&self, version: u64) -> Result<()> {
+        Ok(std::fs::remove_dir_all(self.dir(version))?)
+    }
+    /// Removes all but the `keep` newest builds, never the active one. Taggers still open on
+    /// a removed build keep their mapping of it until they reload.
+    pub fn prune(&self, keep: usize) -> Result<()> {
+        let current = self.current()?;
+        let versions = self.list()?;
+        let stale = versions.len().saturating_sub(keep);
+        for version in versions.into_iter().take(stale) {
+            if current != Some(version) {
+                self.remove(version)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_activate_prune() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-versions-{}", std::process::id()));
+        let versions = Versions::new(&dir);
+        assert_eq!(versions.current().unwrap(), None);
+        assert!(versions.list().unwrap().is_empty());
+
+        let created = (0..4)
+            .map(|_| versions.create().unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(created, vec![1, 2, 3, 4]);
+        assert_eq!(versions.list().unwrap(), created);
+        assert!(versions.activate(9).is_err());
+        versions.activate(1).unwrap();
+        assert_eq!(versions.current().unwrap(), Some(1));
+
+        versions.prune(2).unwrap();
+        assert_eq!(versions.list().unwrap(), vec![1, 3, 4]);
+        assert_eq!(versions.create().unwrap().0, 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_meta_and_diff() {
+        let dir = std::env::temp_dir().join(format!("rest-mecab-diff-{}", std::process::id()));
+        let csv_dir = dir.join("user-dic");
+        std::fs::create_dir_all(&csv_dir).unwrap();
+        let versions = Versions::new(&dir);
+
+        std::fs::write(
+            csv_dir.join("rest-mecab.csv"),
+            "감스트,,,,NNP,*,F,감스트,*,*,*,*\n뤣쉙퀡,,,,NNP,*,T,뤣쉙퀡,*,*,*,*\n",
+        )
+        .unwrap();
+        let (from, _) = versions.create().unwrap();
+        versions.snapshot(from, &csv_dir).unwrap();
+        let meta = Meta {
+            version: from,
+            created_at: now(),
+            entries: 2,
+            source: Some("http://userdic/nouns".to_string()),
+            hash: Some(versions.hash(from).unwrap()),
+            learned_seq: Some(42),
+        };
+        versions.write_meta(&meta).unwrap();
+        assert_eq!(versions.meta(from).unwrap(), meta);
+
+        std::fs::write(
+            csv_dir.join("rest-mecab.csv"),
+            "감스트,,,-100,NNP,*,F,감스트,*,*,*,*\n톩톩,,,,NNP,*,T,톩톩,*,*,*,*\n",
+        )
+        .unwrap();
+        let (to, _) = versions.create().unwrap();
+        versions.snapshot(to, &csv_dir).unwrap();
+
+        assert_eq!(versions.entries(to).unwrap().len(), 2);
+        assert_ne!(versions.hash(from).unwrap(), versions.hash(to).unwrap());
+        let (same, _) = versions.create().unwrap();
+        versions.snapshot(same, &csv_dir).unwrap();
+        assert_eq!(versions.hash(same).unwrap(), versions.hash(to).unwrap());
+
+        let diff = versions.diff(from, to).unwrap();
+        assert_eq!(diff.added, vec!["톩톩"]);
+        assert_eq!(diff.removed, vec!["뤣쉙퀡"]);
+        assert_eq!(diff.changed, vec!["감스트"]);
+        assert!(versions.diff(from, 9).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}